@@ -0,0 +1,332 @@
+use anyhow::{anyhow, Result};
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::metadata::LoopCount;
+use image::{AnimationDecoder, DynamicImage, Frames, ImageFormat, ImageReader};
+use std::io::{BufRead, Cursor, Seek, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Delays below this are treated as unset, like browsers do
+const MIN_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug)]
+pub struct Frame {
+    pub image: DynamicImage,
+    pub delay: Duration,
+}
+
+/// Fully composited frames, disposal is already applied by the decoders
+#[derive(Debug)]
+pub struct Animation {
+    pub frames: Vec<Frame>,
+    /// Times to play the frames, `None` to repeat forever
+    pub loops: Option<u32>,
+}
+
+/// Decodes every frame of an animated GIF, APNG or WebP file
+///
+/// Returns `None` for still images and formats without animation
+pub fn get_animation(file: &str) -> Result<Option<Animation>> {
+    let reader = ImageReader::open(file).map_err(|_| anyhow!("Invalid image path: {}", file))?;
+    decode_animation(reader, file)
+}
+
+/// Like [`get_animation`] for an image held in memory, `name` is used in errors
+pub fn get_animation_from_bytes(bytes: &[u8], name: &str) -> Result<Option<Animation>> {
+    decode_animation(ImageReader::new(Cursor::new(bytes)), name)
+}
+
+/// Number of frames in an image held in memory, 1 for still images
+///
+/// Frames are decoded one at a time and dropped right away rather than
+/// collected
+pub fn count_frames(bytes: &[u8], name: &str) -> Result<usize> {
+    let decode_error = |_| anyhow!("Failed to decode image: {}", name);
+    let Some((mut frames, _)) = frames(ImageReader::new(Cursor::new(bytes)), name)? else {
+        return Ok(1);
+    };
+
+    frames.try_fold(0, |count, frame| {
+        frame.map(|_| count + 1).map_err(decode_error)
+    })
+}
+
+fn decode_animation<R: BufRead + Seek>(
+    reader: ImageReader<R>,
+    name: &str,
+) -> Result<Option<Animation>> {
+    let decode_error = |_| anyhow!("Failed to decode image: {}", name);
+    let Some((frames, loop_count)) = frames(reader, name)? else {
+        return Ok(None);
+    };
+
+    let frames = frames
+        .map(|frame| {
+            let frame = frame.map_err(decode_error)?;
+            let delay = Duration::from(frame.delay());
+            Ok(Frame {
+                delay: if delay < MIN_DELAY {
+                    DEFAULT_DELAY
+                } else {
+                    delay
+                },
+                image: DynamicImage::ImageRgba8(frame.into_buffer()),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if frames.len() < 2 {
+        return Ok(None);
+    }
+
+    let loops = match loop_count {
+        LoopCount::Infinite => None,
+        LoopCount::Finite(n) => Some(n.get()),
+    };
+
+    Ok(Some(Animation { frames, loops }))
+}
+
+/// Frames of an animated GIF, APNG or WebP file along with its loop count
+///
+/// Returns `None` for still images and formats without animation
+fn frames<'a, R: BufRead + Seek + 'a>(
+    reader: ImageReader<R>,
+    name: &str,
+) -> Result<Option<(Frames<'a>, LoopCount)>> {
+    let reader = reader
+        .with_guessed_format()
+        .map_err(|_| anyhow!("Failed to read image: {}", name))?;
+    let format = reader.format();
+    let reader = reader.into_inner();
+    let decode_error = |_| anyhow!("Failed to decode image: {}", name);
+
+    let frames = match format {
+        Some(ImageFormat::Gif) => {
+            let decoder = GifDecoder::new(reader).map_err(decode_error)?;
+            let loop_count = decoder.loop_count();
+            (decoder.into_frames(), loop_count)
+        }
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(reader).map_err(decode_error)?;
+            if !decoder.is_apng().map_err(decode_error)? {
+                return Ok(None);
+            }
+            let decoder = decoder.apng().map_err(decode_error)?;
+            let loop_count = decoder.loop_count();
+            (decoder.into_frames(), loop_count)
+        }
+        Some(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(reader).map_err(decode_error)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            let loop_count = decoder.loop_count();
+            (decoder.into_frames(), loop_count)
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(frames))
+}
+
+/// Plays pre-rendered frames in place, each `rows` terminal rows tall
+///
+/// Returns `false` when playback was stopped with Ctrl-C
+pub fn play(
+    frames: &[(Vec<u8>, Duration)],
+    rows: u32,
+    loops: Option<u32>,
+    out: &mut dyn Write,
+) -> Result<bool> {
+    let rows = rows.max(1);
+    let _guard = InterruptGuard::install();
+
+    // Scroll room for the frames first so redrawing never moves the screen,
+    // then remember where the frames start and hide the cursor
+    write!(
+        out,
+        "{}\x1b[{}A\r\x1b7\x1b[?25l",
+        "\n".repeat(rows as usize),
+        rows
+    )?;
+
+    let mut finished = true;
+    let mut played = 0;
+    'playback: while loops.is_none_or(|loops| played < loops) {
+        for (output, delay) in frames {
+            let start = Instant::now();
+
+            out.write_all(b"\x1b8")?;
+            out.write_all(output)?;
+            out.flush()?;
+
+            if !wait(delay.saturating_sub(start.elapsed())) {
+                finished = false;
+                break 'playback;
+            }
+        }
+        played += 1;
+    }
+
+    // Leave the cursor on the last row of the frames, like a still image
+    write!(out, "\x1b8\r")?;
+    if rows > 1 {
+        write!(out, "\x1b[{}B", rows - 1)?;
+    }
+    write!(out, "\x1b[?25h")?;
+    out.flush()?;
+
+    Ok(finished)
+}
+
+/// Sleeps for `duration`, returns `false` early on Ctrl-C
+fn wait(duration: Duration) -> bool {
+    const SLICE: Duration = Duration::from_millis(10);
+
+    let end = Instant::now() + duration;
+    while let Some(left) = end.checked_duration_since(Instant::now()) {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            return false;
+        }
+        thread::sleep(left.min(SLICE));
+    }
+
+    !INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Turns Ctrl-C into a flag while playing so the cursor can be restored
+struct InterruptGuard;
+
+#[cfg(unix)]
+impl InterruptGuard {
+    fn install() -> Self {
+        extern "C" fn on_interrupt(_: libc::c_int) {
+            INTERRUPTED.store(true, Ordering::Relaxed);
+        }
+
+        INTERRUPTED.store(false, Ordering::Relaxed);
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+        unsafe {
+            libc::signal(
+                libc::SIGINT,
+                on_interrupt as *const () as libc::sighandler_t,
+            );
+        }
+        Self
+    }
+}
+
+#[cfg(unix)]
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        // SAFETY: restores the default disposition
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+impl InterruptGuard {
+    fn install() -> Self {
+        Self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::{GifEncoder, Repeat};
+    use image::{Delay, RgbaImage};
+
+    fn gif(delays: &[u64], repeat: Repeat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut bytes);
+            encoder.set_repeat(repeat).unwrap();
+            for &ms in delays {
+                let frame = image::Frame::from_parts(
+                    RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255])),
+                    0,
+                    0,
+                    Delay::from_saturating_duration(Duration::from_millis(ms)),
+                );
+                encoder.encode_frame(frame).unwrap();
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn short_delays_fall_back() {
+        let bytes = gif(&[0, 10, 20, 50], Repeat::Infinite);
+        let animation = get_animation_from_bytes(&bytes, "test").unwrap().unwrap();
+        let delays: Vec<_> = animation.frames.iter().map(|frame| frame.delay).collect();
+        assert_eq!(
+            delays,
+            [100, 100, 20, 50].map(Duration::from_millis).to_vec()
+        );
+    }
+
+    #[test]
+    fn loop_counts() {
+        let loops = |repeat| {
+            let bytes = gif(&[50, 50], repeat);
+            get_animation_from_bytes(&bytes, "test")
+                .unwrap()
+                .unwrap()
+                .loops
+        };
+        assert_eq!(loops(Repeat::Infinite), None);
+        assert_eq!(loops(Repeat::Finite(3)), Some(3));
+    }
+
+    #[test]
+    fn still_images() {
+        let bytes = gif(&[50], Repeat::Infinite);
+        assert!(get_animation_from_bytes(&bytes, "test").unwrap().is_none());
+
+        let mut png = Vec::new();
+        RgbaImage::new(2, 2)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        assert!(get_animation_from_bytes(&png, "test").unwrap().is_none());
+    }
+
+    #[test]
+    fn frame_counts() {
+        let bytes = gif(&[50, 50, 50], Repeat::Infinite);
+        assert_eq!(count_frames(&bytes, "test").unwrap(), 3);
+        let bytes = gif(&[50], Repeat::Infinite);
+        assert_eq!(count_frames(&bytes, "test").unwrap(), 1);
+
+        let mut png = Vec::new();
+        RgbaImage::new(2, 2)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        assert_eq!(count_frames(&png, "test").unwrap(), 1);
+    }
+
+    #[test]
+    fn play_finite() {
+        let frames = [
+            (b"a".to_vec(), Duration::ZERO),
+            (b"b".to_vec(), Duration::ZERO),
+        ];
+        let mut out = Vec::new();
+        assert!(play(&frames, 2, Some(2), &mut out).unwrap());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\n\n\x1b[2A\r\x1b7\x1b[?25l\
+             \x1b8a\x1b8b\x1b8a\x1b8b\
+             \x1b8\r\x1b[1B\x1b[?25h"
+        );
+    }
+}
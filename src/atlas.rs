@@ -0,0 +1,267 @@
+use crate::layout::Tile;
+use crate::render::{RenderOptions, Renderer};
+use crate::transform::Transform;
+use anyhow::{anyhow, Result};
+use image::DynamicImage;
+use std::ops::RangeInclusive;
+
+/// Layout of equally sized tiles in a sprite sheet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Atlas {
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// Pixels before the first tile at the top and left edge
+    pub margin: u32,
+    /// Pixels between neighbouring tiles
+    pub spacing: u32,
+}
+
+impl Atlas {
+    /// Columns and rows of whole tiles in an image of `width` x `height`
+    ///
+    /// Empty tiles give no columns or rows
+    pub fn grid(&self, width: u32, height: u32) -> (u32, u32) {
+        if self.tile_width == 0 || self.tile_height == 0 {
+            return (0, 0);
+        }
+
+        let count = |length: u32, tile: u32| {
+            // Wide enough that no margin or spacing can overflow
+            let room = length.saturating_sub(self.margin) as u64 + self.spacing as u64;
+            (room / (tile as u64 + self.spacing as u64)) as u32
+        };
+        (
+            count(width, self.tile_width),
+            count(height, self.tile_height),
+        )
+    }
+
+    /// Cuts out tile `index`, counted left to right from the top left
+    ///
+    /// Returns `None` past the last tile
+    pub fn tile(&self, image: &DynamicImage, index: u32) -> Option<DynamicImage> {
+        let (columns, rows) = self.grid(image.width(), image.height());
+        if index >= columns.saturating_mul(rows) {
+            return None;
+        }
+
+        // Tiles that fit lie inside the image, so their position fits in u32
+        let offset = |cell: u32, tile: u32| {
+            (self.margin as u64 + cell as u64 * (tile as u64 + self.spacing as u64)) as u32
+        };
+        let x = offset(index % columns, self.tile_width);
+        let y = offset(index / columns, self.tile_height);
+        Some(image.crop_imm(x, y, self.tile_width, self.tile_height))
+    }
+
+    /// Cuts the selected tiles out of `image`, labelled with their index
+    ///
+    /// The renderer's transform applies to the whole atlas, the tiles are
+    /// then scaled and padded on their own. Without a selection every tile
+    /// is returned.
+    pub fn slice(
+        &self,
+        renderer: &Renderer,
+        image: DynamicImage,
+        selection: Option<&Selection>,
+    ) -> Result<Vec<Tile>> {
+        if self.tile_width == 0 || self.tile_height == 0 {
+            return Err(anyhow!(
+                "Tiles of {}x{} are empty",
+                self.tile_width,
+                self.tile_height
+            ));
+        }
+
+        let image = renderer.options().transform.apply(image)?;
+        let (columns, rows) = self.grid(image.width(), image.height());
+        let count = columns.saturating_mul(rows);
+        if count == 0 {
+            return Err(anyhow!(
+                "Image of {}x{} is smaller than a tile",
+                image.width(),
+                image.height()
+            ));
+        }
+
+        // Check before collecting, a range can be far larger than the atlas
+        if let Some(range) = selection
+            .iter()
+            .flat_map(|s| &s.0)
+            .find(|r| *r.end() >= count)
+        {
+            return Err(anyhow!(
+                "Tile {} is outside the atlas, it has {} tiles",
+                range.end(),
+                count
+            ));
+        }
+        let indices: Vec<_> = match selection {
+            Some(selection) => selection.indices().collect(),
+            None => (0..count).collect(),
+        };
+
+        // The atlas is already transformed, tiles are only scaled and padded
+        let tiler = Renderer::new(RenderOptions {
+            transform: Transform::default(),
+            ..renderer.options().clone()
+        });
+        indices
+            .into_iter()
+            .filter_map(|index| self.tile(&image, index).map(|tile| (index, tile)))
+            .map(|(index, tile)| {
+                Ok(Tile {
+                    image: tiler.prepare(tile)?,
+                    caption: Some(format!("#{}", index)),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Tile indices and ranges to show
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selection(pub Vec<RangeInclusive<u32>>);
+
+impl Selection {
+    /// Every selected index in order, repeats included
+    pub fn indices(&self) -> impl Iterator<Item = u32> + '_ {
+        self.0.iter().flat_map(|range| range.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::{Crop, Flip, Length};
+    use image::{GenericImageView, Rgba, RgbaImage};
+
+    const ATLAS: Atlas = Atlas {
+        tile_width: 2,
+        tile_height: 2,
+        margin: 0,
+        spacing: 0,
+    };
+
+    /// 4x4 atlas of four 2x2 tiles, each one a single shade of gray
+    /// counting up from the top left
+    fn fixture() -> DynamicImage {
+        RgbaImage::from_fn(4, 4, |x, y| {
+            let shade = ((y / 2) * 2 + x / 2) as u8 * 10;
+            Rgba([shade, shade, shade, 255])
+        })
+        .into()
+    }
+
+    /// Caption, shade and size of a sliced tile
+    type Sliced = (String, u8, (u32, u32));
+
+    fn sliced(
+        transform: Transform,
+        selection: Option<Vec<RangeInclusive<u32>>>,
+    ) -> Result<Vec<Sliced>> {
+        let renderer = Renderer::new(RenderOptions {
+            transform,
+            ..RenderOptions::default()
+        });
+        let selection = selection.map(Selection);
+        let tiles = ATLAS.slice(&renderer, fixture(), selection.as_ref())?;
+        Ok(tiles
+            .into_iter()
+            .map(|tile| {
+                let shade = tile.image.get_pixel(0, 0).0[0];
+                (tile.caption.unwrap(), shade, tile.image.dimensions())
+            })
+            .collect())
+    }
+
+    #[test]
+    fn grids() {
+        assert_eq!(ATLAS.grid(4, 4), (2, 2));
+        assert_eq!(ATLAS.grid(5, 3), (2, 1));
+        assert_eq!(ATLAS.grid(1, 4), (0, 2));
+
+        let spaced = Atlas {
+            margin: 1,
+            spacing: 2,
+            ..ATLAS
+        };
+        // 1 + 2 + 2 + 2 + 2 pixels hold two tiles, one more pixel a third
+        assert_eq!(spaced.grid(9, 9), (2, 2));
+        assert_eq!(spaced.grid(13, 10), (3, 2));
+        assert_eq!(spaced.grid(1, 0), (0, 0));
+    }
+
+    #[test]
+    fn grid_extremes() {
+        let huge = |margin, spacing| Atlas {
+            margin,
+            spacing,
+            ..ATLAS
+        };
+        assert_eq!(huge(0, u32::MAX).grid(4, 4), (1, 1));
+        assert_eq!(huge(u32::MAX, 0).grid(4, 4), (0, 0));
+        assert_eq!(huge(0, u32::MAX).grid(u32::MAX, u32::MAX), (1, 1));
+        assert_eq!(ATLAS.grid(u32::MAX, 1), (u32::MAX / 2, 0));
+
+        let empty = Atlas {
+            tile_width: 0,
+            ..ATLAS
+        };
+        assert_eq!(empty.grid(4, 4), (0, 0));
+        assert!(empty.tile(&fixture(), 0).is_none());
+        assert!(empty
+            .slice(&Renderer::new(RenderOptions::default()), fixture(), None)
+            .is_err());
+
+        let spaced = huge(0, u32::MAX);
+        let tile = spaced.tile(&fixture(), 0).unwrap();
+        assert_eq!(tile.dimensions(), (2, 2));
+        assert!(spaced.tile(&fixture(), 1).is_none());
+    }
+
+    #[test]
+    fn slice_tiles() {
+        let tiles = sliced(Transform::default(), Some(vec![3..=3, 1..=2])).unwrap();
+
+        assert_eq!(
+            tiles,
+            [
+                ("#3".into(), 30, (2, 2)),
+                ("#1".into(), 10, (2, 2)),
+                ("#2".into(), 20, (2, 2))
+            ]
+        );
+    }
+
+    #[test]
+    fn slice_out_of_range() {
+        assert!(sliced(Transform::default(), Some(vec![4..=4])).is_err());
+        assert!(sliced(Transform::default(), Some(vec![0..=4_000_000_000])).is_err());
+    }
+
+    #[test]
+    fn slice_transformed_atlas() {
+        // Flipping the atlas swaps the tiles, the tiles themselves stay whole
+        let flip = Transform {
+            flip: Some(Flip::Horizontal),
+            ..Transform::default()
+        };
+        let tiles = sliced(flip, None).unwrap();
+        let shades: Vec<_> = tiles.iter().map(|(_, shade, _)| *shade).collect();
+        assert_eq!(shades, [10, 0, 30, 20]);
+
+        // A crop of the atlas leaves one tile, rather than cropping every tile
+        let crop = Transform {
+            crop: Some(Crop::At {
+                x: Length::Pixels(2),
+                y: Length::Pixels(2),
+                width: Length::Pixels(2),
+                height: Length::Pixels(2),
+            }),
+            ..Transform::default()
+        };
+        let tiles = sliced(crop, None).unwrap();
+        assert_eq!(tiles, [("#0".into(), 30, (2, 2))]);
+    }
+}
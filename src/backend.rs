@@ -0,0 +1,110 @@
+mod blocks;
+mod iterm;
+mod kitty;
+mod sixel;
+
+use crate::color::ColorDepth;
+use crate::detect::TerminalSize;
+use crate::dither::Dither;
+use anyhow::Result;
+use image::RgbaImage;
+use std::io::Write;
+
+pub use blocks::{Blocks, Charset};
+pub use iterm::Iterm;
+pub use kitty::Kitty;
+pub use sixel::Sixel;
+
+/// Cell size assumed for pixel protocols when the terminal doesn't report one
+const DEFAULT_CELL_PIXELS: (u32, u32) = (10, 20);
+
+/// Something that can put an image on the terminal
+///
+/// Backends are shared between threads, so any state they keep between
+/// images should live in atomics
+pub trait Backend: Send + Sync {
+    /// Writes `image` to `out`, pixels that are not fully opaque are left transparent
+    fn render(&self, image: &RgbaImage, out: &mut dyn Write) -> Result<()>;
+
+    /// Like [`Backend::render`], for frame `index` of an animation, drawn
+    /// over the previous frame
+    ///
+    /// Backends whose images would stack up or show through each other
+    /// replace the previous frame instead. Index 0 starts a new animation.
+    fn render_frame(&self, image: &RgbaImage, index: usize, out: &mut dyn Write) -> Result<()> {
+        let _ = index;
+        self.render(image, out)
+    }
+}
+
+/// Terminal output protocol
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Protocol {
+    /// Colored half-block characters, two pixels per cell
+    Blocks,
+    /// DEC sixel graphics
+    Sixel,
+    /// Kitty graphics protocol, also understood by WezTerm and Konsole
+    Kitty,
+    /// iTerm2 inline images, also understood by WezTerm
+    Iterm,
+}
+
+impl Protocol {
+    /// Image pixels covered by one terminal cell as (width, height)
+    pub fn cell_size(self, charset: Charset, terminal: Option<TerminalSize>) -> (u32, u32) {
+        match self {
+            Self::Blocks => charset.cell_size(),
+            Self::Sixel | Self::Kitty | Self::Iterm => terminal
+                .and_then(|t| t.cell_pixels)
+                .unwrap_or(DEFAULT_CELL_PIXELS),
+        }
+    }
+
+    pub fn backend(
+        self,
+        depth: ColorDepth,
+        dither: Dither,
+        charset: Charset,
+        ramp: &str,
+        columns: Option<u32>,
+        rows: Option<u32>,
+    ) -> Box<dyn Backend> {
+        match self {
+            Self::Blocks => Box::new(Blocks {
+                depth,
+                dither,
+                charset,
+                ramp: ramp.chars().collect(),
+            }),
+            Self::Sixel => Box::new(Sixel { dither }),
+            Self::Kitty => Box::new(Kitty::new(columns, rows)),
+            Self::Iterm => Box::new(Iterm { columns, rows }),
+        }
+    }
+}
+
+/// Standard base64 with padding, as expected by terminal image protocols
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - i * 6)) as usize & 63] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
@@ -0,0 +1,422 @@
+use super::Backend;
+use crate::color::{ColorDepth, Oklab};
+use crate::dither::{self, Dither};
+use anstyle::{Color, Style};
+use anyhow::Result;
+use image::RgbaImage;
+use std::io::{self, Write};
+
+/// Characters used to draw pixels inside a cell
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Charset {
+    /// Upper and lower half blocks, 1x2 pixels per cell
+    #[default]
+    Half,
+    /// Quadrant blocks, 2x2 pixels per cell
+    Quadrant,
+    /// Unicode 13 sextants, 2x3 pixels per cell
+    Sextant,
+    /// Braille patterns, 2x4 pixels per cell
+    Braille,
+    /// Characters from a brightness ramp, 1x2 pixels per cell, plain text with mono colors
+    Ascii,
+}
+
+impl Charset {
+    /// Pixels covered by one cell as (width, height)
+    pub fn cell_size(self) -> (u32, u32) {
+        match self {
+            Self::Half => (1, 2),
+            Self::Quadrant => (2, 2),
+            Self::Sextant => (2, 3),
+            Self::Braille => (2, 4),
+            Self::Ascii => (1, 2),
+        }
+    }
+
+    /// Character showing the pixels set in `mask`
+    ///
+    /// Bit `row * width + column` of the mask is the pixel at that position
+    fn glyph(self, mask: u32) -> char {
+        const QUADRANTS: [char; 16] = [
+            ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+        ];
+        // Braille dot bits in cell pixel order
+        const BRAILLE_DOTS: [u32; 8] = [0x01, 0x08, 0x02, 0x10, 0x04, 0x20, 0x40, 0x80];
+
+        match self {
+            Self::Half => [' ', '▀', '▄', '█'][mask as usize],
+            Self::Ascii => unreachable!("ascii characters are picked by brightness"),
+            Self::Quadrant => QUADRANTS[mask as usize],
+            Self::Sextant => match mask {
+                0 => ' ',
+                21 => '▌',
+                42 => '▐',
+                63 => '█',
+                // The sextant block skips the two patterns that already exist as half blocks
+                _ => {
+                    let skipped = (mask > 21) as u32 + (mask > 42) as u32;
+                    char::from_u32(0x1fb00 + mask - 1 - skipped).unwrap()
+                }
+            },
+            Self::Braille => {
+                let dots = (0..8)
+                    .filter(|bit| mask & (1 << bit) != 0)
+                    .fold(0, |dots, bit| dots | BRAILLE_DOTS[bit]);
+                char::from_u32(0x2800 + dots).unwrap()
+            }
+        }
+    }
+}
+
+/// Character cell renderer, every cell shows a small block of pixels
+pub struct Blocks {
+    pub depth: ColorDepth,
+    pub dither: Dither,
+    pub charset: Charset,
+    /// Characters from darkest to brightest for the ascii charset
+    pub ramp: Vec<char>,
+}
+
+impl Backend for Blocks {
+    fn render(&self, image: &RgbaImage, out: &mut dyn Write) -> Result<()> {
+        // The ramp already encodes brightness, dithering would only add noise
+        if self.charset == Charset::Ascii {
+            self.render_ascii(image, out)?;
+            out.flush()?;
+            return Ok(());
+        }
+
+        let mut rgb = image.clone();
+        if let Some(palette) = self.depth.palette() {
+            if self.dither != Dither::None {
+                dither::dither(&mut rgb, palette, self.dither);
+            }
+        }
+
+        match self.charset {
+            Charset::Half => self.render_half(&rgb, out)?,
+            Charset::Quadrant | Charset::Sextant | Charset::Braille => {
+                self.render_cells(&rgb, out)?
+            }
+            Charset::Ascii => unreachable!(),
+        }
+
+        out.flush()?;
+
+        Ok(())
+    }
+}
+
+impl Blocks {
+    fn render_half(&self, rgb: &RgbaImage, out: &mut dyn Write) -> io::Result<()> {
+        let (width, height) = rgb.dimensions();
+        for y in 0..height.div_ceil(2) {
+            if y > 0 {
+                writeln!(out)?;
+            }
+
+            for x in 0..width {
+                let top_pixel = rgb.get_pixel_checked(x, y * 2);
+                let bot_pixel = rgb.get_pixel_checked(x, (y * 2) + 1);
+
+                write_cell(
+                    out,
+                    pixel_to_cell_color(top_pixel, self.depth),
+                    pixel_to_cell_color(bot_pixel, self.depth),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Draws cells of several pixels, each with a foreground and background
+    /// color fitted to the pixels it covers
+    fn render_cells(&self, rgb: &RgbaImage, out: &mut dyn Write) -> io::Result<()> {
+        let (cell_width, cell_height) = self.charset.cell_size();
+        let full = (1 << (cell_width * cell_height)) - 1;
+        let columns = rgb.width().div_ceil(cell_width);
+        let rows = rgb.height().div_ceil(cell_height);
+
+        for row in 0..rows {
+            if row > 0 {
+                writeln!(out)?;
+            }
+
+            for column in 0..columns {
+                let pixels: Vec<_> = (0..cell_height)
+                    .flat_map(|dy| (0..cell_width).map(move |dx| (dx, dy)))
+                    .map(|(dx, dy)| {
+                        rgb.get_pixel_checked(column * cell_width + dx, row * cell_height + dy)
+                            .filter(|p| p.0[3] == 255)
+                            .map(|p| [p.0[0], p.0[1], p.0[2]])
+                    })
+                    .collect();
+
+                let Some((mut mask, fg, bg)) = fit_two_colors(&pixels) else {
+                    write!(out, " ")?;
+                    continue;
+                };
+
+                let mut fg = self.depth.color(fg);
+                let mut bg = bg.and_then(|bg| self.depth.color(bg));
+                if fg == bg {
+                    mask = full;
+                }
+                if fg.is_none() {
+                    std::mem::swap(&mut fg, &mut bg);
+                    mask = !mask & full;
+                }
+
+                let glyph = match fg {
+                    Some(_) => self.charset.glyph(mask),
+                    None => ' ',
+                };
+                let style = Style::new().fg_color(fg.flatten()).bg_color(bg.flatten());
+                write!(out, "{}{}{}", style.render(), glyph, style.render_reset())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Draws one ramp character per cell, picked by the cell's brightness
+    fn render_ascii(&self, rgb: &RgbaImage, out: &mut dyn Write) -> io::Result<()> {
+        let (cell_width, cell_height) = self.charset.cell_size();
+        let columns = rgb.width().div_ceil(cell_width);
+        let rows = rgb.height().div_ceil(cell_height);
+
+        for row in 0..rows {
+            if row > 0 {
+                writeln!(out)?;
+            }
+
+            for column in 0..columns {
+                let opaque: Vec<_> = (0..cell_height)
+                    .flat_map(|dy| (0..cell_width).map(move |dx| (dx, dy)))
+                    .filter_map(|(dx, dy)| {
+                        rgb.get_pixel_checked(column * cell_width + dx, row * cell_height + dy)
+                    })
+                    .filter(|p| p.0[3] == 255)
+                    .map(|p| [p.0[0], p.0[1], p.0[2]])
+                    .collect();
+
+                if opaque.is_empty() {
+                    write!(out, " ")?;
+                    continue;
+                }
+
+                let color = mean(&opaque);
+                let lightness = Oklab::from_srgb(color).l.clamp(0.0, 1.0);
+                let glyph = self.ramp[(lightness * (self.ramp.len() - 1) as f32).round() as usize];
+
+                let style = Style::new().fg_color(self.depth.color(color).flatten());
+                write!(out, "{}{}{}", style.render(), glyph, style.render_reset())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits a cell's pixels into foreground and background
+///
+/// Returns the foreground mask, the foreground color and the background
+/// color, which is `None` when some pixels are transparent. Returns `None`
+/// for fully transparent cells.
+fn fit_two_colors(pixels: &[Option<[u8; 3]>]) -> Option<(u32, [u8; 3], Option<[u8; 3]>)> {
+    let opaque: Vec<_> = pixels.iter().flatten().copied().collect();
+    if opaque.is_empty() {
+        return None;
+    }
+
+    if opaque.len() < pixels.len() {
+        let mask = pixels
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.is_some())
+            .fold(0, |mask, (i, _)| mask | (1 << i));
+        return Some((mask, mean(&opaque), None));
+    }
+
+    let distance = |a: [u8; 3], b: [u8; 3]| -> u32 {
+        (0..3)
+            .map(|c| (a[c] as i32 - b[c] as i32).pow(2) as u32)
+            .sum()
+    };
+
+    // Seed the two clusters with the most distant pair, then refine
+    let (mut fg, mut bg) = opaque
+        .iter()
+        .flat_map(|&a| opaque.iter().map(move |&b| (a, b)))
+        .max_by_key(|&(a, b)| distance(a, b))
+        .unwrap();
+
+    let mut mask = 0;
+    for _ in 0..4 {
+        mask = opaque
+            .iter()
+            .enumerate()
+            .filter(|(_, &p)| distance(p, fg) <= distance(p, bg))
+            .fold(0, |mask, (i, _)| mask | (1 << i));
+
+        let (front, back): (Vec<_>, Vec<_>) = opaque
+            .iter()
+            .enumerate()
+            .partition(|(i, _)| mask & (1 << i) != 0);
+        let front: Vec<_> = front.into_iter().map(|(_, &p)| p).collect();
+        let back: Vec<_> = back.into_iter().map(|(_, &p)| p).collect();
+
+        fg = mean(&front);
+        if back.is_empty() {
+            bg = fg;
+            break;
+        }
+        bg = mean(&back);
+    }
+
+    Some((mask, fg, Some(bg)))
+}
+
+fn mean(colors: &[[u8; 3]]) -> [u8; 3] {
+    let sum = colors.iter().fold([0u32; 3], |acc, c| {
+        [
+            acc[0] + c[0] as u32,
+            acc[1] + c[1] as u32,
+            acc[2] + c[2] as u32,
+        ]
+    });
+    sum.map(|s| (s / colors.len() as u32) as u8)
+}
+
+/// Writes one character cell made of an upper and a lower block
+///
+/// A block is `None` when empty and `Some(None)` when drawn
+/// in the terminal's default foreground color
+fn write_cell(
+    out: &mut dyn Write,
+    upper: Option<Option<Color>>,
+    lower: Option<Option<Color>>,
+) -> io::Result<()> {
+    let (glyph, style) = match (upper, lower) {
+        (Some(Some(upper)), Some(Some(lower))) => (
+            '▀',
+            Style::new().fg_color(Some(upper)).bg_color(Some(lower)),
+        ),
+        (Some(Some(upper)), _) => ('▀', Style::new().fg_color(Some(upper))),
+        (_, Some(Some(lower))) => ('▄', Style::new().fg_color(Some(lower))),
+        (Some(None), Some(None)) => ('█', Style::new()),
+        (Some(None), None) => ('▀', Style::new()),
+        (None, Some(None)) => ('▄', Style::new()),
+        (None, None) => (' ', Style::new()),
+    };
+
+    write!(out, "{}{}{}", style.render(), glyph, style.render_reset())
+}
+
+fn pixel_to_cell_color(
+    pixel_opt: Option<&image::Rgba<u8>>,
+    depth: ColorDepth,
+) -> Option<Option<Color>> {
+    pixel_opt.and_then(|p| {
+        let alpha = p.0[3];
+        if alpha == 255 {
+            depth.color([p.0[0], p.0[1], p.0[2]])
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn sextants() {
+        let glyph = |mask| Charset::Sextant.glyph(mask);
+
+        assert_eq!(glyph(0), ' ');
+        assert_eq!(glyph(1), '\u{1fb00}');
+        assert_eq!(glyph(20), '\u{1fb13}');
+        assert_eq!(glyph(21), '▌');
+        assert_eq!(glyph(22), '\u{1fb14}');
+        assert_eq!(glyph(41), '\u{1fb27}');
+        assert_eq!(glyph(42), '▐');
+        assert_eq!(glyph(43), '\u{1fb28}');
+        assert_eq!(glyph(62), '\u{1fb3b}');
+        assert_eq!(glyph(63), '█');
+
+        // Every other pattern maps onto the 60 sextant characters in order
+        let sextants: Vec<_> = (1..63)
+            .filter(|mask| ![21, 42].contains(mask))
+            .map(glyph)
+            .collect();
+        let expected: Vec<_> = (0x1fb00..=0x1fb3b).filter_map(char::from_u32).collect();
+        assert_eq!(sextants, expected);
+    }
+
+    #[test]
+    fn braille_dots() {
+        let glyph = |mask| Charset::Braille.glyph(mask);
+
+        // Pixels left to right, top to bottom are dots 1, 4, 2, 5, 3, 6, 7, 8
+        let dots: Vec<_> = (0..8).map(|bit| glyph(1 << bit)).collect();
+        assert_eq!(dots, ['⠁', '⠈', '⠂', '⠐', '⠄', '⠠', '⡀', '⢀']);
+        assert_eq!(glyph(0), '⠀');
+        assert_eq!(glyph(0b0101_0101), '⡇');
+        assert_eq!(glyph(0b1010_1010), '⢸');
+        assert_eq!(glyph(0xff), '⣿');
+    }
+
+    fn ascii(ramp: &str, gray: u8) -> String {
+        let blocks = Blocks {
+            depth: ColorDepth::Mono,
+            dither: Dither::None,
+            charset: Charset::Ascii,
+            ramp: ramp.chars().collect(),
+        };
+        let image = RgbaImage::from_pixel(1, 2, Rgba([gray, gray, gray, 255]));
+        let mut out = Vec::new();
+        blocks.render(&image, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn ramp_ends() {
+        assert_eq!(ascii(" .:-=+*#%@", 0), " ");
+        assert_eq!(ascii(" .:-=+*#%@", 255), "@");
+        assert_eq!(ascii("ab", 0), "a");
+        assert_eq!(ascii("ab", 255), "b");
+        assert_eq!(ascii("x", 255), "x");
+    }
+
+    #[test]
+    fn ascii_mono_is_plain_text() {
+        let blocks = Blocks {
+            depth: ColorDepth::Mono,
+            dither: Dither::None,
+            charset: Charset::Ascii,
+            ramp: " .:-=+*#%@".chars().collect(),
+        };
+        let image = RgbaImage::from_fn(4, 4, |x, y| match (x, y) {
+            (0, _) => Rgba([255, 0, 0, 255]),
+            (1, _) => Rgba([0, 0, 0, 0]),
+            _ => Rgba([(x * 60) as u8, 200, (y * 60) as u8, 255]),
+        });
+        let mut out = Vec::new();
+        blocks.render(&image, &mut out).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "* ##\n* ##");
+    }
+
+    #[test]
+    fn ramp_rounds() {
+        // Oklab lightness of 50% sRGB gray is 0.6, which rounds to index 5 of 0 to 9
+        assert_eq!(ascii("0123456789", 128), "5");
+        assert_eq!(ascii("01", 128), "1");
+        assert_eq!(ascii("012", 128), "1");
+    }
+}
@@ -0,0 +1,85 @@
+use super::{base64, Backend};
+use anyhow::Result;
+use image::{ImageFormat, RgbaImage};
+use std::io::{Cursor, Write};
+
+/// iTerm2 inline image renderer, sends every image as a PNG
+pub struct Iterm {
+    /// Columns the image is stretched over, natural size when `None`
+    pub columns: Option<u32>,
+    /// Rows the image is stretched over, natural size when `None`
+    pub rows: Option<u32>,
+}
+
+impl Backend for Iterm {
+    fn render(&self, image: &RgbaImage, out: &mut dyn Write) -> Result<()> {
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+
+        // With one side given, the other follows from the aspect ratio
+        let (width, height) = match (self.columns, self.rows) {
+            (None, None) => (
+                format!("{}px", image.width()),
+                format!("{}px", image.height()),
+            ),
+            (columns, rows) => (cells(columns), cells(rows)),
+        };
+
+        out.write_all(&encode(&png, &width, &height))?;
+        out.flush()?;
+
+        Ok(())
+    }
+}
+
+/// A length in cells, or `auto` without one
+fn cells(length: Option<u32>) -> String {
+    length.map_or_else(|| "auto".into(), |length| length.to_string())
+}
+
+/// Wraps PNG data in an OSC 1337 `File=` sequence
+///
+/// `width` and `height` use iTerm2 units: plain numbers are cells,
+/// `px` suffixed numbers are pixels
+fn encode(png: &[u8], width: &str, height: &str) -> Vec<u8> {
+    format!(
+        "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07",
+        png.len(),
+        width,
+        height,
+        base64(png)
+    )
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn size(columns: Option<u32>, rows: Option<u32>) -> String {
+        let mut out = Vec::new();
+        Iterm { columns, rows }
+            .render(&RgbaImage::new(3, 2), &mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let start = out.find("width=").unwrap();
+        let end = out.find(";preserveAspectRatio").unwrap();
+        out[start..end].to_string()
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(size(None, None), "width=3px;height=2px");
+        assert_eq!(size(Some(20), Some(10)), "width=20;height=10");
+        assert_eq!(size(Some(20), None), "width=20;height=auto");
+        assert_eq!(size(None, Some(10)), "width=auto;height=10");
+    }
+
+    #[test]
+    fn sequence() {
+        assert_eq!(
+            encode(b"png", "1", "2px"),
+            b"\x1b]1337;File=inline=1;size=3;width=1;height=2px;preserveAspectRatio=1:cG5n\x07"
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use super::{base64, Backend};
+use anyhow::Result;
+use image::{ImageFormat, RgbaImage};
+use std::io::{Cursor, Write};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Largest payload the kitty protocol accepts in one escape sequence
+const CHUNK_SIZE: usize = 4096;
+
+/// Kitty graphics protocol renderer, sends every image as a PNG
+pub struct Kitty {
+    /// Columns the image is stretched over, natural size when `None`
+    pub columns: Option<u32>,
+    /// Rows the image is stretched over, natural size when `None`
+    pub rows: Option<u32>,
+    next_id: AtomicU32,
+    /// Id shared by the frames of the animation being drawn
+    frame_id: AtomicU32,
+}
+
+impl Kitty {
+    pub fn new(columns: Option<u32>, rows: Option<u32>) -> Self {
+        // Images live on in the terminal, keep ids apart from earlier runs
+        let first_id = (std::process::id() & 0xffff) << 8 | 1;
+        Self {
+            columns,
+            rows,
+            next_id: AtomicU32::new(first_id),
+            frame_id: AtomicU32::new(0),
+        }
+    }
+
+    fn next_id(&self) -> u32 {
+        self.next_id
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |id| {
+                Some(id.wrapping_add(1).max(1))
+            })
+            .unwrap_or_else(|id| id)
+    }
+}
+
+impl Backend for Kitty {
+    fn render(&self, image: &RgbaImage, out: &mut dyn Write) -> Result<()> {
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+
+        out.write_all(&encode(&png, self.next_id(), self.columns, self.rows))?;
+        out.flush()?;
+
+        Ok(())
+    }
+
+    /// Frames reuse one image id and delete the previous frame first, so
+    /// only one image is left on the screen
+    fn render_frame(&self, image: &RgbaImage, index: usize, out: &mut dyn Write) -> Result<()> {
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+
+        let id = if index == 0 {
+            let id = self.next_id();
+            self.frame_id.store(id, Ordering::Relaxed);
+            id
+        } else {
+            self.frame_id.load(Ordering::Relaxed)
+        };
+
+        write!(out, "\x1b_Ga=d,d=I,i={},q=2\x1b\\", id)?;
+        out.write_all(&encode(&png, id, self.columns, self.rows))?;
+        out.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Wraps PNG data in chunked transmit-and-display commands
+fn encode(png: &[u8], id: u32, columns: Option<u32>, rows: Option<u32>) -> Vec<u8> {
+    let mut control = format!("a=T,f=100,q=2,i={}", id);
+    if let Some(columns) = columns {
+        control += &format!(",c={}", columns);
+    }
+    if let Some(rows) = rows {
+        control += &format!(",r={}", rows);
+    }
+
+    let payload = base64(png);
+    let chunks: Vec<_> = payload.as_bytes().chunks(CHUNK_SIZE).collect();
+
+    let mut out = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        out.extend_from_slice(b"\x1b_G");
+        if i == 0 {
+            out.extend_from_slice(format!("{},m={};", control, more).as_bytes());
+        } else {
+            out.extend_from_slice(format!("m={};", more).as_bytes());
+        }
+        out.extend_from_slice(chunk);
+        out.extend_from_slice(b"\x1b\\");
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits the output into (control data, payload) per escape sequence
+    fn commands(encoded: &[u8]) -> Vec<(String, String)> {
+        let encoded = String::from_utf8(encoded.to_vec()).unwrap();
+        let commands: Vec<_> = encoded
+            .strip_suffix("\x1b\\")
+            .unwrap()
+            .split("\x1b\\")
+            .map(|command| {
+                let (control, payload) = command
+                    .strip_prefix("\x1b_G")
+                    .unwrap()
+                    .split_once(';')
+                    .unwrap();
+                (control.to_string(), payload.to_string())
+            })
+            .collect();
+        commands
+    }
+
+    #[test]
+    fn single_chunk() {
+        let encoded = encode(b"png", 7, None, None);
+
+        assert_eq!(encoded, b"\x1b_Ga=T,f=100,q=2,i=7,m=0;cG5n\x1b\\");
+    }
+
+    #[test]
+    fn placement() {
+        let both = commands(&encode(b"png", 1, Some(20), Some(10)));
+        let columns = commands(&encode(b"png", 1, Some(20), None));
+        let rows = commands(&encode(b"png", 1, None, Some(10)));
+
+        assert_eq!(both[0].0, "a=T,f=100,q=2,i=1,c=20,r=10,m=0");
+        assert_eq!(columns[0].0, "a=T,f=100,q=2,i=1,c=20,m=0");
+        assert_eq!(rows[0].0, "a=T,f=100,q=2,i=1,r=10,m=0");
+    }
+
+    #[test]
+    fn chunked() {
+        // 3 bytes encode to 4 characters, so this is two full chunks and a bit
+        let png = vec![0; CHUNK_SIZE / 4 * 3 * 2 + 1];
+        let commands = commands(&encode(&png, 3, Some(4), None));
+
+        let controls: Vec<_> = commands
+            .iter()
+            .map(|(control, _)| control.as_str())
+            .collect();
+        assert_eq!(controls, ["a=T,f=100,q=2,i=3,c=4,m=1", "m=1", "m=0"]);
+
+        let lengths: Vec<_> = commands.iter().map(|(_, payload)| payload.len()).collect();
+        assert_eq!(lengths, [CHUNK_SIZE, CHUNK_SIZE, 4]);
+
+        let payload: String = commands
+            .iter()
+            .map(|(_, payload)| payload.as_str())
+            .collect();
+        assert_eq!(payload, base64(&png));
+    }
+
+    #[test]
+    fn exact_chunk() {
+        let png = vec![0; CHUNK_SIZE / 4 * 3];
+        let commands = commands(&encode(&png, 3, None, None));
+
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].0, "a=T,f=100,q=2,i=3,m=0");
+    }
+
+    #[test]
+    fn frames_share_an_id() {
+        let kitty = Kitty::new(None, None);
+        let image = RgbaImage::new(1, 1);
+        let frame = |index| {
+            let mut out = Vec::new();
+            kitty.render_frame(&image, index, &mut out).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            let (delete, transmit) = out.split_once("\x1b\\").unwrap();
+            let id = delete
+                .strip_prefix("\x1b_Ga=d,d=I,i=")
+                .unwrap()
+                .strip_suffix(",q=2");
+            let id: u32 = id.unwrap().parse().unwrap();
+            assert!(transmit.starts_with(&format!("\x1b_Ga=T,f=100,q=2,i={},", id)));
+            id
+        };
+
+        let first = frame(0);
+        assert_eq!(frame(1), first);
+        assert_eq!(frame(2), first);
+        let second = frame(0);
+        assert_ne!(second, first);
+        assert_eq!(frame(1), second);
+    }
+
+    #[test]
+    fn ids_advance() {
+        let kitty = Kitty::new(None, None);
+        let image = RgbaImage::new(1, 1);
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let mut out = Vec::new();
+            kitty.render(&image, &mut out).unwrap();
+            let control = commands(&out).remove(0).0;
+            let id = control
+                .split(',')
+                .find_map(|key| key.strip_prefix("i="))
+                .unwrap();
+            ids.push(id.parse::<u32>().unwrap());
+        }
+
+        assert_ne!(ids[0], 0);
+        assert_eq!(ids[1], ids[0] + 1);
+    }
+}
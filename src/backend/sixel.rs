@@ -0,0 +1,233 @@
+use super::Backend;
+use crate::color::Palette;
+use crate::dither::{self, Dither};
+use anyhow::Result;
+use image::RgbaImage;
+use std::io::Write;
+
+/// Most sixel terminals offer 256 color registers
+const MAX_COLORS: usize = 256;
+
+/// Sixel renderer, one terminal pixel per image pixel
+pub struct Sixel {
+    pub dither: Dither,
+}
+
+impl Backend for Sixel {
+    fn render(&self, image: &RgbaImage, out: &mut dyn Write) -> Result<()> {
+        self.draw(image, true, out)
+    }
+
+    /// Fills transparent pixels with the background, so the previous frame
+    /// doesn't show through
+    fn render_frame(&self, image: &RgbaImage, _index: usize, out: &mut dyn Write) -> Result<()> {
+        self.draw(image, false, out)
+    }
+}
+
+impl Sixel {
+    fn draw(&self, image: &RgbaImage, transparent: bool, out: &mut dyn Write) -> Result<()> {
+        let opaque = image.pixels().filter(|p| p.0[3] == 255);
+        let palette = Palette::median_cut(opaque.map(|p| [p.0[0], p.0[1], p.0[2]]), MAX_COLORS);
+
+        let mut image = image.clone();
+        dither::dither(&mut image, &palette, self.dither);
+
+        let indices: Vec<Option<usize>> = image
+            .pixels()
+            .map(|p| (p.0[3] == 255).then(|| palette.nearest([p.0[0], p.0[1], p.0[2]])))
+            .collect();
+
+        let (width, height) = image.dimensions();
+        out.write_all(&encode(&indices, width, height, &palette, transparent))?;
+        out.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Encodes palette indices as a sixel stream, `None` marks pixels that are
+/// left alone when `transparent` and filled with the background otherwise
+fn encode(
+    indices: &[Option<usize>],
+    width: u32,
+    height: u32,
+    palette: &Palette,
+    transparent: bool,
+) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let mut out = Vec::new();
+
+    // P2 = 1 keeps unpainted pixels transparent
+    // The second parameter picks whether unset pixels keep what was there
+    let background = if transparent { 1 } else { 0 };
+    out.extend_from_slice(format!("\x1bP0;{};0q", background).as_bytes());
+    out.extend_from_slice(format!("\"1;1;{};{}", width, height).as_bytes());
+
+    for (i, [r, g, b]) in palette.colors().iter().enumerate() {
+        let percent = |c: &u8| *c as u32 * 100 / 255;
+        out.extend_from_slice(
+            format!("#{};2;{};{};{}", i, percent(r), percent(g), percent(b)).as_bytes(),
+        );
+    }
+
+    for band in (0..height).step_by(6) {
+        let rows = (height - band).min(6);
+
+        let mut used = vec![false; palette.len()];
+        for y in band..band + rows {
+            for &index in indices[y * width..(y + 1) * width].iter().flatten() {
+                used[index] = true;
+            }
+        }
+
+        let mut first = true;
+        for color in (0..palette.len()).filter(|&c| used[c]) {
+            if !first {
+                out.push(b'$');
+            }
+            first = false;
+
+            out.extend_from_slice(format!("#{}", color).as_bytes());
+
+            let sixels = (0..width).map(|x| {
+                (0..rows)
+                    .filter(|&dy| indices[(band + dy) * width + x] == Some(color))
+                    .fold(0u8, |bits, dy| bits | (1 << dy))
+            });
+            write_runs(&mut out, sixels);
+        }
+
+        out.push(b'-');
+    }
+
+    out.extend_from_slice(b"\x1b\\");
+    out
+}
+
+/// Writes sixel characters, collapsing repeats with the `!` introducer
+fn write_runs(out: &mut Vec<u8>, sixels: impl Iterator<Item = u8>) {
+    let flush = |out: &mut Vec<u8>, bits: u8, count: usize| {
+        let char = b'?' + bits;
+        if count > 3 {
+            out.extend_from_slice(format!("!{}", count).as_bytes());
+            out.push(char);
+        } else {
+            out.extend(std::iter::repeat_n(char, count));
+        }
+    };
+
+    let mut run: Option<(u8, usize)> = None;
+    for bits in sixels {
+        run = match run {
+            Some((current, count)) if current == bits => Some((current, count + 1)),
+            Some((current, count)) => {
+                flush(out, current, count);
+                Some((bits, 1))
+            }
+            None => Some((bits, 1)),
+        };
+    }
+
+    // Trailing empty sixels carry no information
+    if let Some((bits, count)) = run.filter(|&(bits, _)| bits != 0) {
+        flush(out, bits, count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const RED: [u8; 3] = [255, 0, 0];
+    const BLUE: [u8; 3] = [0, 0, 255];
+
+    fn encode_str(
+        indices: &[Option<usize>],
+        width: u32,
+        height: u32,
+        colors: &[[u8; 3]],
+    ) -> String {
+        let palette = Palette::new(colors.to_vec());
+        String::from_utf8(encode(indices, width, height, &palette, true)).unwrap()
+    }
+
+    #[test]
+    fn solid_image() {
+        let image = RgbaImage::from_pixel(2, 3, Rgba([255, 0, 0, 255]));
+        let mut out = Vec::new();
+        Sixel {
+            dither: Dither::None,
+        }
+        .render(&image, &mut out)
+        .unwrap();
+
+        assert_eq!(out, b"\x1bP0;1;0q\"1;1;2;3#0;2;100;0;0#0FF-\x1b\\");
+    }
+
+    #[test]
+    fn bands_of_six_rows() {
+        let indices = vec![Some(0); 8];
+
+        assert_eq!(
+            encode_str(&indices, 1, 8, &[RED]),
+            "\x1bP0;1;0q\"1;1;1;8#0;2;100;0;0#0~-#0B-\x1b\\"
+        );
+    }
+
+    #[test]
+    fn runs_longer_than_three() {
+        assert_eq!(
+            encode_str(&[Some(0); 3], 3, 1, &[RED]),
+            "\x1bP0;1;0q\"1;1;3;1#0;2;100;0;0#0@@@-\x1b\\"
+        );
+        assert_eq!(
+            encode_str(&[Some(0); 5], 5, 1, &[RED]),
+            "\x1bP0;1;0q\"1;1;5;1#0;2;100;0;0#0!5@-\x1b\\"
+        );
+    }
+
+    #[test]
+    fn transparent_pixels() {
+        let indices = [Some(0), None, Some(1), Some(0)];
+
+        assert_eq!(
+            encode_str(&indices, 4, 1, &[RED, BLUE]),
+            "\x1bP0;1;0q\"1;1;4;1#0;2;100;0;0#1;2;0;0;100#0@??@$#1??@-\x1b\\"
+        );
+    }
+
+    #[test]
+    fn trailing_empty_sixels() {
+        let indices = [Some(0), None, None, None, None];
+
+        assert_eq!(
+            encode_str(&indices, 5, 1, &[RED]),
+            "\x1bP0;1;0q\"1;1;5;1#0;2;100;0;0#0@-\x1b\\"
+        );
+    }
+
+    #[test]
+    fn frame_fills_background() {
+        let image = RgbaImage::from_fn(2, 1, |x, _| Rgba([255, 0, 0, x as u8 * 255]));
+        let mut out = Vec::new();
+        Sixel {
+            dither: Dither::None,
+        }
+        .render_frame(&image, 0, &mut out)
+        .unwrap();
+
+        assert_eq!(out, b"\x1bP0;0;0q\"1;1;2;1#0;2;100;0;0#0?@-\x1b\\");
+    }
+
+    #[test]
+    fn fully_transparent_band() {
+        let indices = [None; 2];
+
+        assert_eq!(
+            encode_str(&indices, 2, 1, &[RED]),
+            "\x1bP0;1;0q\"1;1;2;1#0;2;100;0;0-\x1b\\"
+        );
+    }
+}
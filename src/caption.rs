@@ -0,0 +1,97 @@
+use crate::load::{display_name, Loaded};
+use anyhow::Result;
+use image::ImageFormat;
+use std::io::Write;
+use std::path::Path;
+
+/// Placement of captions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum CaptionPosition {
+    Above,
+    #[default]
+    Below,
+}
+
+/// Fills in the placeholders of a caption template
+///
+/// `{name}`, `{path}`, `{width}`, `{height}` and `{format}` are replaced,
+/// animations report the size of their first frame
+pub fn caption(template: &str, path: &Path, format: Option<ImageFormat>, image: &Loaded) -> String {
+    let (width, height) = image.dimensions();
+    let format = format.map_or("unknown".into(), |f| format!("{:?}", f).to_uppercase());
+
+    template
+        .replace("{name}", &display_name(path))
+        .replace("{path}", &path.to_string_lossy())
+        .replace("{width}", &width.to_string())
+        .replace("{height}", &height.to_string())
+        .replace("{format}", &format)
+}
+
+/// Writes `caption` cut to `columns` above or below the output of `draw`
+pub fn captioned(
+    caption: Option<String>,
+    position: CaptionPosition,
+    columns: u32,
+    out: &mut dyn Write,
+    draw: impl FnOnce(&mut dyn Write) -> Result<()>,
+) -> Result<()> {
+    let caption: Option<String> = caption.map(|c| c.chars().take(columns as usize).collect());
+
+    if let (Some(caption), CaptionPosition::Above) = (&caption, position) {
+        writeln!(out, "{}", caption)?;
+    }
+    draw(out)?;
+    if let (Some(caption), CaptionPosition::Below) = (&caption, position) {
+        write!(out, "\n{}", caption)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::DynamicImage;
+
+    #[test]
+    fn placeholders() {
+        let image = Loaded::Still(DynamicImage::new_rgb8(64, 32));
+        let path = Path::new("sprites/hero.png");
+
+        assert_eq!(
+            caption(
+                "{name} {path} {width}x{height} {format}",
+                path,
+                Some(ImageFormat::Png),
+                &image
+            ),
+            "hero.png sprites/hero.png 64x32 PNG"
+        );
+        assert_eq!(caption("{format}", path, None, &image), "unknown");
+        assert_eq!(caption("{name}", Path::new("-"), None, &image), "stdin");
+        assert_eq!(caption("{size} {name", path, None, &image), "{size} {name");
+    }
+
+    fn output(caption: &str, position: CaptionPosition, columns: u32) -> String {
+        let mut out = Vec::new();
+        captioned(Some(caption.into()), position, columns, &mut out, |out| {
+            write!(out, "image")?;
+            Ok(())
+        })
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(output("hero.png", CaptionPosition::Below, 4), "image\nhero");
+        assert_eq!(output("hero.png", CaptionPosition::Above, 4), "hero\nimage");
+        assert_eq!(
+            output("hero.png", CaptionPosition::Below, 80),
+            "image\nhero.png"
+        );
+        // Cut by characters, not bytes
+        assert_eq!(output("héros", CaptionPosition::Below, 2), "image\nhé");
+    }
+}
@@ -0,0 +1,420 @@
+use anstyle::{Ansi256Color, Color, RgbColor};
+use std::env;
+use std::sync::OnceLock;
+
+/// How many colors the output may use
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorDepth {
+    /// 24-bit RGB
+    Truecolor,
+    /// xterm 256 color palette
+    #[value(name = "256")]
+    Ansi256,
+    /// The 16 basic ANSI colors
+    #[value(name = "16")]
+    Ansi16,
+    /// No colors, only the terminal's foreground
+    Mono,
+}
+
+impl ColorDepth {
+    /// Guesses the color depth from `NO_COLOR`, `COLORTERM` and `TERM`
+    pub fn detect() -> Self {
+        Self::from_env(|key| env::var(key).ok())
+    }
+
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        if var("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            return Self::Mono;
+        }
+
+        let colorterm = var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return Self::Truecolor;
+        }
+
+        let term = var("TERM").unwrap_or_default();
+        if term.is_empty() || term == "dumb" {
+            Self::Mono
+        } else if term.ends_with("-direct") || term.contains("truecolor") {
+            Self::Truecolor
+        } else if term.contains("256color") {
+            Self::Ansi256
+        } else {
+            Self::Ansi16
+        }
+    }
+
+    /// Palette the output is quantized to, `None` for truecolor
+    pub fn palette(self) -> Option<&'static Palette> {
+        static ANSI256: OnceLock<Palette> = OnceLock::new();
+        static ANSI16: OnceLock<Palette> = OnceLock::new();
+        static MONO: OnceLock<Palette> = OnceLock::new();
+
+        match self {
+            Self::Truecolor => None,
+            Self::Ansi256 => Some(ANSI256.get_or_init(|| Palette::new(xterm256()))),
+            Self::Ansi16 => Some(ANSI16.get_or_init(|| Palette::new(XTERM16.to_vec()))),
+            Self::Mono => Some(MONO.get_or_init(|| Palette::new(vec![[0, 0, 0], [255, 255, 255]]))),
+        }
+    }
+
+    /// Maps a pixel to the closest color this depth can display
+    ///
+    /// Follows the block convention used for cells: `None` leaves the
+    /// pixel unlit and `Some(None)` lights it in the default foreground
+    pub fn color(self, rgb: [u8; 3]) -> Option<Option<Color>> {
+        let [r, g, b] = rgb;
+        let Some(palette) = self.palette() else {
+            return Some(Some(Color::Rgb(RgbColor(r, g, b))));
+        };
+
+        let index = palette.nearest(rgb);
+        match self {
+            Self::Truecolor => unreachable!(),
+            // Skip the 16 system colors, their values depend on the terminal theme
+            Self::Ansi256 => Some(Some(Color::Ansi256(Ansi256Color(index as u8 + 16)))),
+            Self::Ansi16 => Some(Ansi256Color(index as u8).into_ansi().map(Color::Ansi)),
+            Self::Mono => (index == 1).then_some(None),
+        }
+    }
+}
+
+/// Parses `#rgb` or `#rrggbb`, the `#` is optional
+pub fn parse_hex(color: &str) -> Result<[u8; 3], String> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    let invalid = || format!("Invalid color: {}", color);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).map_err(|_| invalid());
+    match hex.len() {
+        3 => {
+            let [r, g, b] = [&hex[0..1], &hex[1..2], &hex[2..3]].map(channel);
+            Ok([r? * 17, g? * 17, b? * 17])
+        }
+        6 => {
+            let [r, g, b] = [&hex[0..2], &hex[2..4], &hex[4..6]].map(channel);
+            Ok([r?, g?, b?])
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Default xterm values of the 16 basic colors
+const XTERM16: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [205, 0, 0],
+    [0, 205, 0],
+    [205, 205, 0],
+    [0, 0, 238],
+    [205, 0, 205],
+    [0, 205, 205],
+    [229, 229, 229],
+    [127, 127, 127],
+    [255, 0, 0],
+    [0, 255, 0],
+    [255, 255, 0],
+    [92, 92, 255],
+    [255, 0, 255],
+    [0, 255, 255],
+    [255, 255, 255],
+];
+
+/// Colors 16 through 255 of the xterm palette: a 6x6x6 cube and a gray ramp
+fn xterm256() -> Vec<[u8; 3]> {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+    let mut colors = Vec::with_capacity(240);
+    for r in LEVELS {
+        for g in LEVELS {
+            for b in LEVELS {
+                colors.push([r, g, b]);
+            }
+        }
+    }
+    colors.extend((0..24).map(|i| [8 + i * 10; 3]));
+    colors
+}
+
+/// A fixed set of colors with nearest-color lookup in Oklab space
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+    lab: Vec<Oklab>,
+}
+
+impl Palette {
+    pub fn new(colors: Vec<[u8; 3]>) -> Self {
+        let lab = colors.iter().map(|&c| Oklab::from_srgb(c)).collect();
+        Self { colors, lab }
+    }
+
+    /// Builds a palette of at most `max` colors by recursively splitting
+    /// the color box with the widest channel at its median
+    pub fn median_cut(pixels: impl Iterator<Item = [u8; 3]>, max: usize) -> Self {
+        let mut pixels: Vec<[u8; 3]> = pixels.collect();
+        pixels.sort_unstable();
+
+        let mut unique = pixels.clone();
+        unique.dedup();
+        if unique.len() <= max {
+            return Self::new(unique);
+        }
+
+        let mut boxes = vec![pixels];
+        while boxes.len() < max {
+            let widest = boxes
+                .iter()
+                .enumerate()
+                .filter(|(_, b)| b.len() > 1)
+                .map(|(i, b)| (i, widest_channel(b)))
+                .max_by_key(|&(_, (_, range))| range);
+
+            let Some((index, (channel, range))) = widest else {
+                break;
+            };
+            if range == 0 {
+                break;
+            }
+
+            let mut colors = boxes.swap_remove(index);
+            colors.sort_unstable_by_key(|c| c[channel]);
+            let upper = colors.split_off(colors.len() / 2);
+            boxes.push(colors);
+            boxes.push(upper);
+        }
+
+        let colors = boxes
+            .iter()
+            .filter(|b| !b.is_empty())
+            .map(|b| {
+                let sum = b.iter().fold([0u64; 3], |acc, c| {
+                    [
+                        acc[0] + c[0] as u64,
+                        acc[1] + c[1] as u64,
+                        acc[2] + c[2] as u64,
+                    ]
+                });
+                sum.map(|s| (s / b.len() as u64) as u8)
+            })
+            .collect();
+
+        Self::new(colors)
+    }
+
+    pub fn colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> [u8; 3] {
+        self.colors[index]
+    }
+
+    /// Index of the perceptually closest palette entry
+    pub fn nearest(&self, rgb: [u8; 3]) -> usize {
+        let target = Oklab::from_srgb(rgb);
+        self.lab
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.distance(&target).total_cmp(&b.distance(&target)))
+            .map(|(i, _)| i)
+            .unwrap_or(0)
+    }
+}
+
+/// Channel with the largest spread and the size of that spread
+fn widest_channel(colors: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let (min, max) = colors.iter().fold((255, 0), |(min, max), c| {
+                (c[channel].min(min), c[channel].max(max))
+            });
+            (channel, max - min)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap()
+}
+
+/// A color in the Oklab perceptual color space
+#[derive(Clone, Copy, Debug)]
+pub struct Oklab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+impl Oklab {
+    pub fn from_srgb([r, g, b]: [u8; 3]) -> Self {
+        let (r, g, b) = (srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b));
+
+        let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+        let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+        let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+
+        Self {
+            l: 0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+            a: 1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+            b: 0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+        }
+    }
+
+    /// Squared euclidean distance
+    pub fn distance(&self, other: &Self) -> f32 {
+        let (dl, da, db) = (self.l - other.l, self.a - other.a, self.b - other.b);
+        dl * dl + da * da + db * db
+    }
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anstyle::AnsiColor;
+
+    fn detect(vars: &[(&str, &str)]) -> ColorDepth {
+        ColorDepth::from_env(|key| {
+            vars.iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn detect_from_env() {
+        use ColorDepth::*;
+
+        assert_eq!(detect(&[]), Mono);
+        assert_eq!(detect(&[("TERM", "dumb")]), Mono);
+        assert_eq!(detect(&[("TERM", "linux")]), Ansi16);
+        assert_eq!(detect(&[("TERM", "xterm-256color")]), Ansi256);
+        assert_eq!(detect(&[("TERM", "xterm-direct")]), Truecolor);
+        assert_eq!(
+            detect(&[("TERM", "xterm"), ("COLORTERM", "truecolor")]),
+            Truecolor
+        );
+        assert_eq!(
+            detect(&[("TERM", "dumb"), ("COLORTERM", "24bit")]),
+            Truecolor
+        );
+        assert_eq!(detect(&[("TERM", "xterm"), ("COLORTERM", "yes")]), Ansi16);
+        assert_eq!(
+            detect(&[
+                ("TERM", "xterm-direct"),
+                ("COLORTERM", "truecolor"),
+                ("NO_COLOR", "1")
+            ]),
+            Mono
+        );
+        assert_eq!(
+            detect(&[("TERM", "xterm-256color"), ("NO_COLOR", "")]),
+            Ansi256
+        );
+    }
+
+    #[test]
+    fn ansi256() {
+        let color = |rgb| ColorDepth::Ansi256.color(rgb);
+
+        // Indices 16 to 231 are the cube, 232 to 255 the gray ramp
+        assert_eq!(color([0, 0, 0]), Some(Some(Ansi256Color(16).into())));
+        assert_eq!(color([255, 0, 0]), Some(Some(Ansi256Color(196).into())));
+        assert_eq!(color([255, 255, 255]), Some(Some(Ansi256Color(231).into())));
+        assert_eq!(color([128, 128, 128]), Some(Some(Ansi256Color(244).into())));
+        assert_eq!(color([8, 8, 8]), Some(Some(Ansi256Color(232).into())));
+        assert_eq!(color([100, 140, 180]), Some(Some(Ansi256Color(67).into())));
+    }
+
+    #[test]
+    fn ansi16() {
+        let color = |rgb| ColorDepth::Ansi16.color(rgb);
+
+        assert_eq!(color([200, 10, 10]), Some(Some(AnsiColor::Red.into())));
+        assert_eq!(color([255, 0, 0]), Some(Some(AnsiColor::BrightRed.into())));
+        assert_eq!(color([0, 0, 0]), Some(Some(AnsiColor::Black.into())));
+        assert_eq!(
+            color([250, 250, 250]),
+            Some(Some(AnsiColor::BrightWhite.into()))
+        );
+    }
+
+    #[test]
+    fn mono() {
+        assert_eq!(ColorDepth::Mono.color([20, 20, 20]), None);
+        assert_eq!(ColorDepth::Mono.color([230, 230, 230]), Some(None));
+        assert!(ColorDepth::Truecolor.palette().is_none());
+        assert_eq!(
+            ColorDepth::Truecolor.color([1, 2, 3]),
+            Some(Some(Color::Rgb(RgbColor(1, 2, 3))))
+        );
+    }
+
+    #[test]
+    fn nearest_is_perceptual() {
+        let palette = Palette::new(vec![[0, 0, 0], [255, 255, 255], [128, 128, 128]]);
+
+        assert_eq!(palette.nearest([120, 120, 120]), 2);
+        // Gray 60 is closer to black in RGB, but looks closer to gray 128
+        assert_eq!(palette.nearest([60, 60, 60]), 2);
+        assert_eq!(palette.nearest([30, 30, 30]), 0);
+        assert_eq!(palette.nearest([240, 240, 240]), 1);
+        assert_eq!(Palette::new(Vec::new()).nearest([1, 2, 3]), 0);
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(parse_hex("#ff8000"), Ok([255, 128, 0]));
+        assert_eq!(parse_hex("FF8000"), Ok([255, 128, 0]));
+        assert_eq!(parse_hex("#f80"), Ok([255, 136, 0]));
+        assert_eq!(parse_hex("abc"), Ok([170, 187, 204]));
+        assert!(parse_hex("#ff80").is_err());
+        assert!(parse_hex("#gg0000").is_err());
+        assert!(parse_hex("#+f+f+f").is_err());
+        assert!(parse_hex("").is_err());
+        assert!(parse_hex("#éé").is_err());
+    }
+
+    #[test]
+    fn median_cut_keeps_few_colors() {
+        let pixels = [[1, 2, 3], [4, 5, 6], [1, 2, 3]];
+        let palette = Palette::median_cut(pixels.into_iter(), 4);
+
+        assert_eq!(palette.colors(), [[1, 2, 3], [4, 5, 6]]);
+    }
+
+    #[test]
+    fn median_cut_splits_clusters() {
+        let pixels = (0..10u8)
+            .map(|i| [i, 0, 0])
+            .chain((0..10u8).map(|i| [200 + i, 0, 0]));
+        let palette = Palette::median_cut(pixels, 2);
+
+        let mut colors = palette.colors().to_vec();
+        colors.sort();
+        assert_eq!(colors, [[4, 0, 0], [204, 0, 0]]);
+    }
+
+    #[test]
+    fn median_cut_limit() {
+        let pixels = (0..=255u8).flat_map(|r| [[r, 0, 255 - r], [0, r, r / 2]]);
+        let palette = Palette::median_cut(pixels, 16);
+
+        assert_eq!(palette.len(), 16);
+    }
+}
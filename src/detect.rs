@@ -0,0 +1,519 @@
+use crate::backend::Protocol;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::time::{Duration, Instant};
+
+/// How long to wait for the terminal to answer the probes
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Kitty graphics query for a 1x1 RGB image, answered only by supporting terminals
+const KITTY_QUERY: &[u8] = b"\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\";
+const KITTY_OK: &[u8] = b"\x1b_Gi=31;OK";
+
+/// Primary device attributes, answered by practically every terminal
+const DA1_QUERY: &[u8] = b"\x1b[c";
+/// DA1 attribute announcing sixel graphics
+const DA1_SIXEL: u32 = 4;
+
+/// Asks for the default background color
+const OSC11_QUERY: &[u8] = b"\x1b]11;?\x1b\\";
+const OSC11_REPLY: &[u8] = b"\x1b]11;rgb:";
+
+/// Connection to a terminal that answers queries
+pub trait Tty {
+    fn send(&mut self, data: &[u8]) -> io::Result<()>;
+
+    /// Reads available reply bytes, waiting at most `timeout`
+    ///
+    /// Returns `Ok(0)` when nothing arrived in time
+    fn recv(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize>;
+}
+
+/// Graphics support announced by the terminal
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub kitty: bool,
+    pub sixel: bool,
+}
+
+/// Size of the terminal window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TerminalSize {
+    pub columns: u32,
+    pub rows: u32,
+    /// Pixels per cell as (width, height), when the terminal reports them
+    pub cell_pixels: Option<(u32, u32)>,
+}
+
+/// Queries the terminal size, falling back to `COLUMNS` and `LINES`
+pub fn terminal_size() -> Option<TerminalSize> {
+    window_size().or_else(|| {
+        let var = |key| env::var(key).ok()?.parse().ok();
+        Some(TerminalSize {
+            columns: var("COLUMNS")?,
+            rows: var("LINES")?,
+            cell_pixels: None,
+        })
+    })
+}
+
+#[cfg(unix)]
+fn window_size() -> Option<TerminalSize> {
+    use std::os::fd::AsRawFd;
+
+    let ioctl = |fd| {
+        // SAFETY: TIOCGWINSZ only writes a winsize struct
+        let size = unsafe {
+            let mut size: libc::winsize = std::mem::zeroed();
+            (libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) == 0).then_some(size)
+        }?;
+        (size.ws_col > 0 && size.ws_row > 0).then_some(size)
+    };
+
+    // Output may be piped while the terminal is still around
+    let size = ioctl(libc::STDOUT_FILENO)
+        .or_else(|| ioctl(libc::STDERR_FILENO))
+        .or_else(|| ioctl(std::fs::File::open("/dev/tty").ok()?.as_raw_fd()))?;
+
+    let (columns, rows) = (size.ws_col as u32, size.ws_row as u32);
+    let cell_pixels = (size.ws_xpixel > 0 && size.ws_ypixel > 0).then(|| {
+        (
+            size.ws_xpixel as u32 / columns,
+            size.ws_ypixel as u32 / rows,
+        )
+    });
+
+    Some(TerminalSize {
+        columns,
+        rows,
+        cell_pixels,
+    })
+}
+
+#[cfg(not(unix))]
+fn window_size() -> Option<TerminalSize> {
+    None
+}
+
+/// Picks the best protocol for the terminal on stdout
+///
+/// Only probes when stdout is a terminal, otherwise half-blocks are used
+pub fn protocol() -> Protocol {
+    if !io::stdout().is_terminal() {
+        return Protocol::Blocks;
+    }
+
+    let capabilities = DevTty::open()
+        .and_then(|mut tty| query(&mut tty, TIMEOUT))
+        .ok();
+    choose(|key| env::var(key).ok(), capabilities)
+}
+
+/// Sends the graphics probes and collects the replies
+pub fn query(tty: &mut dyn Tty, timeout: Duration) -> io::Result<Capabilities> {
+    let reply = exchange(tty, KITTY_QUERY, timeout)?;
+    Ok(parse_reply(&reply))
+}
+
+/// Asks the terminal on /dev/tty for its background color with OSC 11
+pub fn background() -> Option<[u8; 3]> {
+    let mut tty = DevTty::open().ok()?;
+    let reply = exchange(&mut tty, OSC11_QUERY, TIMEOUT).ok()?;
+    parse_background(&reply)
+}
+
+/// Sends `request` followed by DA1 and collects the replies
+///
+/// Stops reading as soon as the DA1 reply arrives, since terminals
+/// answer in order and DA1 is sent last
+fn exchange(tty: &mut dyn Tty, request: &[u8], timeout: Duration) -> io::Result<Vec<u8>> {
+    tty.send(request)?;
+    tty.send(DA1_QUERY)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut reply = Vec::new();
+    let mut buf = [0; 256];
+
+    while da1_attributes(&reply).is_none() {
+        let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+            break;
+        };
+
+        let read = tty.recv(&mut buf, left)?;
+        if read == 0 {
+            break;
+        }
+        reply.extend_from_slice(&buf[..read]);
+    }
+
+    Ok(reply)
+}
+
+fn parse_reply(reply: &[u8]) -> Capabilities {
+    Capabilities {
+        kitty: find(reply, KITTY_OK).is_some(),
+        sixel: da1_attributes(reply).is_some_and(|attrs| attrs.contains(&DA1_SIXEL)),
+    }
+}
+
+/// Color of an `OSC 11 ; rgb:R/G/B` reply with 1 to 4 hex digits per channel
+fn parse_background(reply: &[u8]) -> Option<[u8; 3]> {
+    let start = find(reply, OSC11_REPLY)? + OSC11_REPLY.len();
+    let len = reply[start..]
+        .iter()
+        .position(|&b| b == b'\x07' || b == b'\x1b')?;
+    let spec = std::str::from_utf8(&reply[start..start + len]).ok()?;
+
+    let mut channels = spec.split('/').map(|digits| {
+        if digits.len() > 4 {
+            return None;
+        }
+        let value = u32::from_str_radix(digits, 16).ok()?;
+        let max = (1u32 << (4 * digits.len())) - 1;
+        Some((value * 255 + max / 2) / max)
+    });
+    let mut next = || channels.next().flatten().map(|v| v as u8);
+    Some([next()?, next()?, next()?])
+}
+
+/// Chooses a protocol from terminal replies and environment hints
+///
+/// `capabilities` is `None` when the terminal could not be probed.
+/// `COLORTERM` is left to [`ColorDepth::detect`](crate::color::ColorDepth::detect),
+/// it only announces the color depth and says nothing about graphics support.
+pub fn choose(
+    var: impl Fn(&str) -> Option<String>,
+    capabilities: Option<Capabilities>,
+) -> Protocol {
+    let capabilities = capabilities.unwrap_or_default();
+    let term = var("TERM").unwrap_or_default();
+    let term_program = var("TERM_PROGRAM").unwrap_or_default();
+
+    if capabilities.kitty || term == "xterm-kitty" || var("KITTY_WINDOW_ID").is_some() {
+        Protocol::Kitty
+    } else if term_program == "iTerm.app" || term_program == "WezTerm" {
+        Protocol::Iterm
+    } else if capabilities.sixel {
+        Protocol::Sixel
+    } else {
+        Protocol::Blocks
+    }
+}
+
+/// Parameters of a `CSI ? ... c` reply
+fn da1_attributes(reply: &[u8]) -> Option<Vec<u32>> {
+    let start = find(reply, b"\x1b[?")? + 3;
+    let len = reply[start..].iter().position(|&b| b == b'c')?;
+
+    let params = std::str::from_utf8(&reply[start..start + len]).ok()?;
+    Some(params.split(';').filter_map(|p| p.parse().ok()).collect())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// The controlling terminal, put in non-canonical mode while open
+#[cfg(unix)]
+pub struct DevTty {
+    file: std::fs::File,
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl DevTty {
+    pub fn open() -> io::Result<Self> {
+        use std::os::fd::AsRawFd;
+
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")?;
+        let fd = file.as_raw_fd();
+
+        // SAFETY: `fd` is open for the lifetime of `file` and termios is plain data
+        let original = unsafe {
+            let mut original = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            if libc::tcsetattr(fd, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            original
+        };
+
+        Ok(Self { file, original })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for DevTty {
+    fn drop(&mut self) {
+        use std::os::fd::AsRawFd;
+
+        // SAFETY: restores the attributes read in `open` on the same descriptor
+        unsafe {
+            libc::tcsetattr(self.file.as_raw_fd(), libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Tty for DevTty {
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        use std::io::Write;
+
+        self.file.write_all(data)?;
+        self.file.flush()
+    }
+
+    fn recv(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        use std::io::Read;
+        use std::os::fd::AsRawFd;
+
+        let mut poll = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = timeout.as_millis().min(i32::MAX as u128) as i32;
+
+        // SAFETY: `poll` points to a single valid pollfd
+        match unsafe { libc::poll(&mut poll, 1, millis) } {
+            -1 => Err(io::Error::last_os_error()),
+            0 => Ok(0),
+            _ => self.file.read(buf),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub struct DevTty;
+
+#[cfg(not(unix))]
+impl DevTty {
+    pub fn open() -> io::Result<Self> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+#[cfg(not(unix))]
+impl Tty for DevTty {
+    fn send(&mut self, _data: &[u8]) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    fn recv(&mut self, _buf: &mut [u8], _timeout: Duration) -> io::Result<usize> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    const KITTY_REPLY: &[u8] = b"\x1b_Gi=31;OK\x1b\\";
+
+    /// Terminal that answers with scripted replies, one per read
+    ///
+    /// `None` stands for a read that times out
+    #[derive(Default)]
+    struct FakeTty {
+        replies: VecDeque<Option<&'static [u8]>>,
+        sent: Vec<u8>,
+        reads: usize,
+    }
+
+    impl FakeTty {
+        fn new(replies: impl IntoIterator<Item = Option<&'static [u8]>>) -> Self {
+            Self {
+                replies: replies.into_iter().collect(),
+                ..Self::default()
+            }
+        }
+    }
+
+    impl Tty for FakeTty {
+        fn send(&mut self, data: &[u8]) -> io::Result<()> {
+            self.sent.extend_from_slice(data);
+            Ok(())
+        }
+
+        fn recv(&mut self, buf: &mut [u8], _timeout: Duration) -> io::Result<usize> {
+            self.reads += 1;
+            let Some(reply) = self.replies.pop_front().flatten() else {
+                return Ok(0);
+            };
+            buf[..reply.len()].copy_from_slice(reply);
+            Ok(reply.len())
+        }
+    }
+
+    fn query_with(replies: impl IntoIterator<Item = Option<&'static [u8]>>) -> Capabilities {
+        query(&mut FakeTty::new(replies), TIMEOUT).unwrap()
+    }
+
+    #[test]
+    fn kitty_and_sixel() {
+        let mut tty = FakeTty::new([Some(KITTY_REPLY), Some(b"\x1b[?62;4c".as_slice())]);
+        let capabilities = query(&mut tty, TIMEOUT).unwrap();
+
+        assert_eq!(
+            capabilities,
+            Capabilities {
+                kitty: true,
+                sixel: true
+            }
+        );
+        assert_eq!(tty.sent, [KITTY_QUERY, DA1_QUERY].concat());
+    }
+
+    #[test]
+    fn da1_only() {
+        assert_eq!(
+            query_with([Some(b"\x1b[?62;22c".as_slice())]),
+            Capabilities::default()
+        );
+        assert_eq!(
+            query_with([Some(b"\x1b[?62;4;22c".as_slice())]),
+            Capabilities {
+                kitty: false,
+                sixel: true
+            }
+        );
+    }
+
+    #[test]
+    fn no_reply() {
+        let mut tty = FakeTty::new([None, Some(b"\x1b[?62;4c".as_slice())]);
+
+        assert_eq!(query(&mut tty, TIMEOUT).unwrap(), Capabilities::default());
+        assert_eq!(tty.reads, 1);
+    }
+
+    #[test]
+    fn deadline_passed() {
+        let mut tty = FakeTty::new([Some(b"\x1b[?62;4c".as_slice())]);
+
+        assert_eq!(
+            query(&mut tty, Duration::ZERO).unwrap(),
+            Capabilities::default()
+        );
+        assert_eq!(tty.reads, 0);
+    }
+
+    #[test]
+    fn split_replies() {
+        let mut tty = FakeTty::new(
+            [
+                b"\x1b_Gi=3".as_slice(),
+                b"1;OK\x1b\\\x1b[".as_slice(),
+                b"?6".as_slice(),
+                b"2;4".as_slice(),
+                b"c".as_slice(),
+            ]
+            .map(Some),
+        );
+
+        assert_eq!(
+            query(&mut tty, TIMEOUT).unwrap(),
+            Capabilities {
+                kitty: true,
+                sixel: true
+            }
+        );
+        assert_eq!(tty.reads, 5);
+    }
+
+    #[test]
+    fn stops_at_da1() {
+        let mut tty = FakeTty::new([Some(b"\x1b[?62c".as_slice()), Some(KITTY_REPLY)]);
+
+        assert_eq!(query(&mut tty, TIMEOUT).unwrap(), Capabilities::default());
+        assert_eq!(tty.replies.len(), 1);
+    }
+
+    #[test]
+    fn background_reply() {
+        let mut tty = FakeTty::new([
+            Some(b"\x1b]11;rgb:ffff/8080/".as_slice()),
+            Some(b"0000\x1b\\\x1b[?62c".as_slice()),
+        ]);
+        let reply = exchange(&mut tty, OSC11_QUERY, TIMEOUT).unwrap();
+
+        assert_eq!(parse_background(&reply), Some([255, 128, 0]));
+        assert_eq!(tty.sent, [OSC11_QUERY, DA1_QUERY].concat());
+    }
+
+    #[test]
+    fn background_digits() {
+        assert_eq!(
+            parse_background(b"\x1b]11;rgb:f/8/0\x07"),
+            Some([255, 136, 0])
+        );
+        assert_eq!(
+            parse_background(b"\x1b]11;rgb:ff/80/00\x07"),
+            Some([255, 128, 0])
+        );
+        assert_eq!(parse_background(b"\x1b]11;rgb:ff/80\x07"), None);
+        assert_eq!(parse_background(b"\x1b]11;rgb:fffff/0/0\x07"), None);
+        assert_eq!(parse_background(b"\x1b[?62c"), None);
+    }
+
+    #[test]
+    fn choose_table() {
+        const KITTY: Option<Capabilities> = Some(Capabilities {
+            kitty: true,
+            sixel: false,
+        });
+        const SIXEL: Option<Capabilities> = Some(Capabilities {
+            kitty: false,
+            sixel: true,
+        });
+        const NONE: Option<Capabilities> = Some(Capabilities {
+            kitty: false,
+            sixel: false,
+        });
+
+        type Vars = &'static [(&'static str, &'static str)];
+        let cases: &[(Vars, Option<Capabilities>, Protocol)] = &[
+            (&[], None, Protocol::Blocks),
+            (&[], NONE, Protocol::Blocks),
+            (&[], KITTY, Protocol::Kitty),
+            (&[], SIXEL, Protocol::Sixel),
+            (&[("TERM", "xterm-kitty")], None, Protocol::Kitty),
+            (&[("KITTY_WINDOW_ID", "1")], None, Protocol::Kitty),
+            (&[("KITTY_WINDOW_ID", "1")], SIXEL, Protocol::Kitty),
+            (&[("TERM_PROGRAM", "iTerm.app")], None, Protocol::Iterm),
+            (&[("TERM_PROGRAM", "WezTerm")], SIXEL, Protocol::Iterm),
+            (&[("TERM_PROGRAM", "WezTerm")], KITTY, Protocol::Kitty),
+            (
+                &[("TERM_PROGRAM", "Apple_Terminal")],
+                None,
+                Protocol::Blocks,
+            ),
+            (&[("TERM", "xterm-256color")], SIXEL, Protocol::Sixel),
+            (&[("COLORTERM", "truecolor")], None, Protocol::Blocks),
+        ];
+
+        for (vars, capabilities, protocol) in cases {
+            let var = |key: &str| {
+                vars.iter()
+                    .find(|(name, _)| *name == key)
+                    .map(|(_, value)| value.to_string())
+            };
+            assert_eq!(
+                choose(var, *capabilities),
+                *protocol,
+                "{:?} {:?}",
+                vars,
+                capabilities
+            );
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use crate::color::Palette;
+use image::RgbaImage;
+use std::sync::OnceLock;
+
+/// How to spread quantization error when the palette is limited
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Dither {
+    /// Plain nearest color
+    #[default]
+    None,
+    /// Ordered dithering with a 2x2 Bayer matrix
+    Bayer2,
+    /// Ordered dithering with a 4x4 Bayer matrix
+    Bayer4,
+    /// Ordered dithering with an 8x8 Bayer matrix
+    Bayer8,
+    /// Floyd-Steinberg error diffusion
+    FloydSteinberg,
+    /// Atkinson error diffusion, keeps more contrast
+    Atkinson,
+    /// Ordered dithering with a blue noise threshold map
+    BlueNoise,
+}
+
+/// Error diffusion kernel as (dx, dy, weight)
+type Kernel = &'static [(i32, i32, f32)];
+
+const FLOYD_STEINBERG: Kernel = &[
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0),
+    (0, 1, 5.0 / 16.0),
+    (1, 1, 1.0 / 16.0),
+];
+
+const ATKINSON: Kernel = &[
+    (1, 0, 1.0 / 8.0),
+    (2, 0, 1.0 / 8.0),
+    (-1, 1, 1.0 / 8.0),
+    (0, 1, 1.0 / 8.0),
+    (1, 1, 1.0 / 8.0),
+    (0, 2, 1.0 / 8.0),
+];
+
+/// Replaces every opaque pixel with a palette color, spreading the error
+/// according to `method`
+///
+/// Pixels that are not fully opaque are left untouched
+pub fn dither(image: &mut RgbaImage, palette: &Palette, method: Dither) {
+    match method {
+        Dither::None => ordered(image, palette, &[0.5], 1),
+        Dither::Bayer2 => ordered(image, palette, &bayer(2), 2),
+        Dither::Bayer4 => ordered(image, palette, &bayer(4), 4),
+        Dither::Bayer8 => ordered(image, palette, &bayer(8), 8),
+        Dither::BlueNoise => ordered(image, palette, blue_noise(), BLUE_NOISE_SIZE),
+        Dither::FloydSteinberg => diffuse(image, palette, FLOYD_STEINBERG),
+        Dither::Atkinson => diffuse(image, palette, ATKINSON),
+    }
+}
+
+/// Offsets each pixel by a tiled threshold map in `[0, 1)` before quantizing
+fn ordered(image: &mut RgbaImage, palette: &Palette, map: &[f32], size: usize) {
+    let spread = 255.0 / (palette.len() as f32).cbrt();
+
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if pixel.0[3] != 255 {
+            continue;
+        }
+
+        let threshold = map[(y as usize % size) * size + x as usize % size];
+        let offset = (threshold - 0.5) * spread;
+        let rgb = [0, 1, 2].map(|c| (pixel.0[c] as f32 + offset).clamp(0.0, 255.0) as u8);
+
+        let [r, g, b] = palette.get(palette.nearest(rgb));
+        pixel.0 = [r, g, b, 255];
+    }
+}
+
+fn diffuse(image: &mut RgbaImage, palette: &Palette, kernel: Kernel) {
+    let (width, height) = (image.width() as i32, image.height() as i32);
+    let mut error = vec![[0.0f32; 3]; (width * height) as usize];
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = image.get_pixel_mut(x as u32, y as u32);
+            if pixel.0[3] != 255 {
+                continue;
+            }
+
+            let index = (y * width + x) as usize;
+            let wanted = [0, 1, 2].map(|c| (pixel.0[c] as f32 + error[index][c]).clamp(0.0, 255.0));
+            let [r, g, b] = palette.get(palette.nearest(wanted.map(|c| c as u8)));
+            pixel.0 = [r, g, b, 255];
+
+            let diff = [
+                wanted[0] - r as f32,
+                wanted[1] - g as f32,
+                wanted[2] - b as f32,
+            ];
+            for &(dx, dy, weight) in kernel {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || nx >= width || ny >= height {
+                    continue;
+                }
+
+                let target = &mut error[(ny * width + nx) as usize];
+                for c in 0..3 {
+                    target[c] += diff[c] * weight;
+                }
+            }
+        }
+    }
+}
+
+/// Normalized Bayer threshold map of `size` x `size`, `size` a power of two
+fn bayer(size: usize) -> Vec<f32> {
+    let mut map = vec![0u32];
+    let mut n = 1;
+    while n < size {
+        let mut next = vec![0; n * n * 4];
+        for y in 0..n {
+            for x in 0..n {
+                let v = map[y * n + x] * 4;
+                next[y * 2 * n + x] = v;
+                next[y * 2 * n + x + n] = v + 2;
+                next[(y + n) * 2 * n + x] = v + 3;
+                next[(y + n) * 2 * n + x + n] = v + 1;
+            }
+        }
+        map = next;
+        n *= 2;
+    }
+
+    let cells = (size * size) as f32;
+    map.into_iter().map(|v| (v as f32 + 0.5) / cells).collect()
+}
+
+const BLUE_NOISE_SIZE: usize = 32;
+
+/// Blue noise threshold map generated once with the void-and-cluster method
+fn blue_noise() -> &'static [f32] {
+    static MAP: OnceLock<Vec<f32>> = OnceLock::new();
+    MAP.get_or_init(|| void_and_cluster(BLUE_NOISE_SIZE))
+}
+
+fn void_and_cluster(size: usize) -> Vec<f32> {
+    const SIGMA: f32 = 1.5;
+    let cells = size * size;
+
+    // Gaussian falloff by toroidal offset
+    let kernel: Vec<f32> = (0..cells)
+        .map(|i| {
+            let wrap = |d: usize| d.min(size - d) as f32;
+            let (dx, dy) = (wrap(i % size), wrap(i / size));
+            (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+        })
+        .collect();
+
+    let mut ones = vec![false; cells];
+    let mut energy = vec![0.0f32; cells];
+    let splat = |energy: &mut [f32], at: usize, sign: f32| {
+        let (ax, ay) = (at % size, at / size);
+        for (i, e) in energy.iter_mut().enumerate() {
+            let dx = (i % size + size - ax) % size;
+            let dy = (i / size + size - ay) % size;
+            *e += sign * kernel[dy * size + dx];
+        }
+    };
+    let tightest_cluster = |ones: &[bool], energy: &[f32]| {
+        (0..cells)
+            .filter(|&i| ones[i])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+    let largest_void = |ones: &[bool], energy: &[f32]| {
+        (0..cells)
+            .filter(|&i| !ones[i])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+
+    // Deterministic sparse starting pattern
+    let mut state = 0x2545_f491_u32;
+    let initial = cells / 10;
+    while ones.iter().filter(|&&o| o).count() < initial {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        let at = state as usize % cells;
+        if !ones[at] {
+            ones[at] = true;
+            splat(&mut energy, at, 1.0);
+        }
+    }
+
+    // Move points from clusters into voids until the pattern settles, which
+    // takes far fewer swaps than there are cells
+    for _ in 0..cells {
+        let cluster = tightest_cluster(&ones, &energy);
+        ones[cluster] = false;
+        splat(&mut energy, cluster, -1.0);
+
+        let void = largest_void(&ones, &energy);
+        ones[void] = true;
+        splat(&mut energy, void, 1.0);
+
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0usize; cells];
+
+    // Rank the initial points by removing the tightest clusters first
+    let (mut phase_ones, mut phase_energy) = (ones.clone(), energy.clone());
+    for r in (0..initial).rev() {
+        let cluster = tightest_cluster(&phase_ones, &phase_energy);
+        phase_ones[cluster] = false;
+        splat(&mut phase_energy, cluster, -1.0);
+        rank[cluster] = r;
+    }
+
+    // Rank the remaining cells by filling the largest voids
+    for r in initial..cells {
+        let void = largest_void(&ones, &energy);
+        ones[void] = true;
+        splat(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+
+    rank.into_iter()
+        .map(|r| (r as f32 + 0.5) / cells as f32)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const METHODS: [Dither; 7] = [
+        Dither::None,
+        Dither::Bayer2,
+        Dither::Bayer4,
+        Dither::Bayer8,
+        Dither::FloydSteinberg,
+        Dither::Atkinson,
+        Dither::BlueNoise,
+    ];
+
+    /// Ranks of a threshold map, undoing the normalization
+    fn ranks(map: &[f32]) -> Vec<u32> {
+        let cells = map.len() as f32;
+        map.iter()
+            .map(|t| (t * cells - 0.5).round() as u32)
+            .collect()
+    }
+
+    #[test]
+    fn bayer_matrices() {
+        assert_eq!(ranks(&bayer(1)), [0]);
+        assert_eq!(ranks(&bayer(2)), [0, 2, 3, 1]);
+        assert_eq!(
+            ranks(&bayer(4)),
+            [0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5]
+        );
+        assert_eq!(bayer(2), [0.125, 0.625, 0.875, 0.375]);
+    }
+
+    #[test]
+    fn threshold_maps_are_permutations() {
+        for map in [bayer(8), void_and_cluster(8), blue_noise().to_vec()] {
+            let mut ranks = ranks(&map);
+            ranks.sort_unstable();
+            assert_eq!(ranks, (0..map.len() as u32).collect::<Vec<_>>());
+        }
+    }
+
+    /// Gradient with a translucent and a transparent column on the right
+    fn gradient() -> RgbaImage {
+        RgbaImage::from_fn(16, 8, |x, y| match x {
+            14 => Rgba([200, 100, 50, 128]),
+            15 => Rgba([1, 2, 3, 0]),
+            _ => Rgba([(x * 16) as u8, (y * 32) as u8, 128, 255]),
+        })
+    }
+
+    #[test]
+    fn outputs_palette_colors() {
+        let palette = Palette::new(vec![[0, 0, 0], [255, 255, 255], [255, 0, 0], [0, 0, 255]]);
+
+        for method in METHODS {
+            let mut image = gradient();
+            dither(&mut image, &palette, method);
+
+            for pixel in image.pixels().filter(|p| p.0[3] == 255) {
+                let rgb = [pixel.0[0], pixel.0[1], pixel.0[2]];
+                assert!(palette.colors().contains(&rgb), "{:?} {:?}", method, rgb);
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_translucent_pixels() {
+        let palette = Palette::new(vec![[0, 0, 0], [255, 255, 255]]);
+
+        for method in METHODS {
+            let mut image = gradient();
+            dither(&mut image, &palette, method);
+
+            for y in 0..8 {
+                assert_eq!(
+                    *image.get_pixel(14, y),
+                    Rgba([200, 100, 50, 128]),
+                    "{:?}",
+                    method
+                );
+                assert_eq!(*image.get_pixel(15, y), Rgba([1, 2, 3, 0]), "{:?}", method);
+            }
+        }
+    }
+
+    #[test]
+    fn mixes_colors() {
+        // Mid gray between black and white comes out as a pattern of both
+        let palette = Palette::new(vec![[0, 0, 0], [255, 255, 255]]);
+
+        for method in &METHODS[1..] {
+            let mut image = RgbaImage::from_pixel(8, 8, Rgba([128, 128, 128, 255]));
+            dither(&mut image, &palette, *method);
+
+            let white = image.pixels().filter(|p| p.0[0] == 255).count();
+            assert!((16..=48).contains(&white), "{:?} {}", method, white);
+        }
+    }
+}
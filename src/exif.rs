@@ -0,0 +1,384 @@
+//! Just enough TIFF to list the common EXIF fields
+
+/// Tag of the pointer from IFD0 to the EXIF sub-IFD
+const EXIF_IFD: u16 = 0x8769;
+
+/// Fields worth showing, in the order they usually appear
+const TAGS: &[(u16, &str)] = &[
+    (0x010e, "Description"),
+    (0x010f, "Make"),
+    (0x0110, "Model"),
+    (0x0112, "Orientation"),
+    (0x011a, "XResolution"),
+    (0x011b, "YResolution"),
+    (0x0128, "ResolutionUnit"),
+    (0x0131, "Software"),
+    (0x0132, "DateTime"),
+    (0x013b, "Artist"),
+    (0x8298, "Copyright"),
+    (0x829a, "ExposureTime"),
+    (0x829d, "FNumber"),
+    (0x8822, "ExposureProgram"),
+    (0x8827, "ISO"),
+    (0x9003, "DateTimeOriginal"),
+    (0x9004, "DateTimeDigitized"),
+    (0x9209, "Flash"),
+    (0x920a, "FocalLength"),
+    (0xa001, "ColorSpace"),
+    (0xa002, "PixelXDimension"),
+    (0xa003, "PixelYDimension"),
+    (0xa405, "FocalLengthIn35mm"),
+    (0xa433, "LensMake"),
+    (0xa434, "LensModel"),
+];
+
+/// Known fields of a raw EXIF chunk as (name, value)
+///
+/// Malformed or unknown entries are skipped
+pub fn fields(chunk: &[u8]) -> Vec<(&'static str, String)> {
+    let chunk = chunk.strip_prefix(b"Exif\0\0").unwrap_or(chunk);
+    let mut fields = Vec::new();
+    if let Some(tiff) = Tiff::new(chunk) {
+        if let Some(ifd0) = tiff.u32(4) {
+            tiff.read_ifd(ifd0 as usize, true, &mut fields);
+        }
+    }
+    fields
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let big_endian = match data.get(..4)? {
+            b"II*\0" => false,
+            b"MM\0*" => true,
+            _ => return None,
+        };
+        Some(Self { data, big_endian })
+    }
+
+    fn bytes<const N: usize>(&self, offset: usize) -> Option<[u8; N]> {
+        let mut bytes: [u8; N] = self
+            .data
+            .get(offset..offset.checked_add(N)?)?
+            .try_into()
+            .ok()?;
+        if !self.big_endian {
+            bytes.reverse();
+        }
+        Some(bytes)
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        self.bytes(offset).map(u16::from_be_bytes)
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        self.bytes(offset).map(u32::from_be_bytes)
+    }
+
+    /// Collects the known entries of the IFD at `offset`, descending into
+    /// the EXIF sub-IFD when `nested` is set
+    fn read_ifd(&self, offset: usize, nested: bool, fields: &mut Vec<(&'static str, String)>) {
+        let Some(count) = self.u16(offset) else {
+            return;
+        };
+
+        for i in 0..count as usize {
+            let entry = offset + 2 + i * 12;
+            let (Some(tag), Some(kind), Some(count)) =
+                (self.u16(entry), self.u16(entry + 2), self.u32(entry + 4))
+            else {
+                return;
+            };
+
+            if tag == EXIF_IFD {
+                if let (true, Some(sub)) = (nested, self.u32(entry + 8)) {
+                    self.read_ifd(sub as usize, false, fields);
+                }
+                continue;
+            }
+
+            let Some(&(_, name)) = TAGS.iter().find(|&&(known, _)| known == tag) else {
+                continue;
+            };
+            if let Some(value) = self.value(entry, kind, count as usize) {
+                fields.push((name, value));
+            }
+        }
+    }
+
+    /// Formats the value of the entry at `entry`, values of up to four bytes
+    /// are stored in the entry itself
+    fn value(&self, entry: usize, kind: u16, count: usize) -> Option<String> {
+        let size = match kind {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 => 4,
+            5 | 10 => 8,
+            _ => return None,
+        };
+        let len = count.checked_mul(size)?;
+        let start = if len <= 4 {
+            entry + 8
+        } else {
+            self.u32(entry + 8)? as usize
+        };
+        let data = self.data.get(start..start.checked_add(len)?)?;
+
+        let values: Vec<String> = match kind {
+            2 | 7 => {
+                let text = String::from_utf8_lossy(data);
+                let text = text.trim_end_matches('\0').trim();
+                return (!text.is_empty() && !text.contains('\0')).then(|| text.into());
+            }
+            1 => data.iter().map(u8::to_string).collect(),
+            6 => data.iter().map(|&b| (b as i8).to_string()).collect(),
+            3 => (0..count)
+                .map(|i| self.u16(start + i * 2).map(|v| v.to_string()))
+                .collect::<Option<_>>()?,
+            8 => (0..count)
+                .map(|i| self.u16(start + i * 2).map(|v| (v as i16).to_string()))
+                .collect::<Option<_>>()?,
+            4 => (0..count)
+                .map(|i| self.u32(start + i * 4).map(|v| v.to_string()))
+                .collect::<Option<_>>()?,
+            9 => (0..count)
+                .map(|i| self.u32(start + i * 4).map(|v| (v as i32).to_string()))
+                .collect::<Option<_>>()?,
+            5 | 10 => (0..count)
+                .map(|i| {
+                    let (numerator, denominator) =
+                        (self.u32(start + i * 8)?, self.u32(start + i * 8 + 4)?);
+                    if kind == 10 {
+                        rational(numerator as i32 as f64, denominator as i32 as f64)
+                    } else {
+                        rational(numerator as f64, denominator as f64)
+                    }
+                })
+                .collect::<Option<_>>()?,
+            _ => unreachable!(),
+        };
+
+        Some(values.join(", "))
+    }
+}
+
+/// Shows exposure style fractions as `1/125` and everything else as a decimal
+fn rational(numerator: f64, denominator: f64) -> Option<String> {
+    if denominator == 0.0 {
+        return None;
+    }
+    if numerator == 1.0 && denominator > 1.0 {
+        return Some(format!("1/{}", denominator));
+    }
+
+    let value = format!("{:.3}", numerator / denominator);
+    Some(value.trim_end_matches('0').trim_end_matches('.').into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An IFD entry as (tag, type, count, value bytes)
+    type Entry = (u16, u16, u32, Vec<u8>);
+
+    /// Writes numbers in either byte order
+    struct Order(bool);
+
+    impl Order {
+        fn u16(&self, value: u16) -> Vec<u8> {
+            if self.0 {
+                value.to_be_bytes().to_vec()
+            } else {
+                value.to_le_bytes().to_vec()
+            }
+        }
+
+        fn u32(&self, value: u32) -> Vec<u8> {
+            if self.0 {
+                value.to_be_bytes().to_vec()
+            } else {
+                value.to_le_bytes().to_vec()
+            }
+        }
+
+        fn rational(&self, numerator: u32, denominator: u32) -> Vec<u8> {
+            [self.u32(numerator), self.u32(denominator)].concat()
+        }
+
+        /// An IFD placed at `offset`, followed by the values that don't fit
+        /// in their entries
+        fn ifd(&self, offset: u32, entries: &[Entry]) -> Vec<u8> {
+            let mut data_offset = offset + 2 + entries.len() as u32 * 12 + 4;
+            let mut ifd = self.u16(entries.len() as u16);
+            let mut data: Vec<u8> = Vec::new();
+            for (tag, kind, count, value) in entries {
+                ifd.extend(self.u16(*tag));
+                ifd.extend(self.u16(*kind));
+                ifd.extend(self.u32(*count));
+                if value.len() <= 4 {
+                    let mut inline = value.clone();
+                    inline.resize(4, 0);
+                    ifd.extend(inline);
+                } else {
+                    ifd.extend(self.u32(data_offset));
+                    data_offset += value.len() as u32;
+                    data.extend(value);
+                }
+            }
+            ifd.extend(self.u32(0));
+            ifd.extend(data);
+            ifd
+        }
+
+        /// A TIFF header and IFD0 with `entries`, and an EXIF sub-IFD with
+        /// `exif` when given
+        fn tiff(&self, entries: &[Entry], exif: Option<&[Entry]>) -> Vec<u8> {
+            let mut entries = entries.to_vec();
+            if exif.is_some() {
+                entries.push((EXIF_IFD, 4, 1, self.u32(0)));
+            }
+            let length = self.ifd(8, &entries).len() as u32;
+            if exif.is_some() {
+                entries.last_mut().unwrap().3 = self.u32(8 + length);
+            }
+
+            let mut tiff = if self.0 { b"MM\0*" } else { b"II*\0" }.to_vec();
+            tiff.extend(self.u32(8));
+            tiff.extend(self.ifd(8, &entries));
+            if let Some(exif) = exif {
+                tiff.extend(self.ifd(8 + length, exif));
+            }
+            tiff
+        }
+    }
+
+    fn camera(order: &Order) -> Vec<u8> {
+        order.tiff(
+            &[
+                (0x010f, 2, 6, b"Canon\0".to_vec()),
+                (0x0110, 2, 4, b"R5\0\0".to_vec()),
+                (0x0112, 3, 1, order.u16(6)),
+                (0x011a, 5, 1, order.rational(72, 1)),
+                (0x0213, 3, 1, order.u16(1)),
+            ],
+            Some(&[
+                (0x829a, 5, 1, order.rational(1, 125)),
+                (0x829d, 5, 1, order.rational(28, 10)),
+                (0x8827, 3, 2, [order.u16(100), order.u16(200)].concat()),
+            ]),
+        )
+    }
+
+    fn expected() -> Vec<(&'static str, String)> {
+        [
+            ("Make", "Canon"),
+            ("Model", "R5"),
+            ("Orientation", "6"),
+            ("XResolution", "72"),
+            ("ExposureTime", "1/125"),
+            ("FNumber", "2.8"),
+            ("ISO", "100, 200"),
+        ]
+        .map(|(name, value)| (name, value.to_string()))
+        .to_vec()
+    }
+
+    #[test]
+    fn byte_orders() {
+        assert_eq!(fields(&camera(&Order(false))), expected());
+        assert_eq!(fields(&camera(&Order(true))), expected());
+    }
+
+    #[test]
+    fn exif_prefix() {
+        let chunk = [b"Exif\0\0".to_vec(), camera(&Order(false))].concat();
+        assert_eq!(fields(&chunk), expected());
+    }
+
+    #[test]
+    fn inline_and_offset_values() {
+        let order = Order(true);
+        let tiff = order.tiff(
+            &[
+                // Four bytes are stored in the entry, five point elsewhere
+                (0x0131, 2, 4, b"abc\0".to_vec()),
+                (0x013b, 2, 5, b"abcd\0".to_vec()),
+                (0xa002, 4, 1, order.u32(4000)),
+                (
+                    0xa003,
+                    9,
+                    2,
+                    [order.u32(-3i32 as u32), order.u32(7)].concat(),
+                ),
+            ],
+            None,
+        );
+
+        assert_eq!(
+            fields(&tiff),
+            [
+                ("Software", "abc".to_string()),
+                ("Artist", "abcd".to_string()),
+                ("PixelXDimension", "4000".to_string()),
+                ("PixelYDimension", "-3, 7".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rationals() {
+        assert_eq!(rational(1.0, 125.0).as_deref(), Some("1/125"));
+        assert_eq!(rational(28.0, 10.0).as_deref(), Some("2.8"));
+        assert_eq!(rational(72.0, 1.0).as_deref(), Some("72"));
+        assert_eq!(rational(1.0, 1.0).as_deref(), Some("1"));
+        assert_eq!(rational(1.0, 3.0).as_deref(), Some("1/3"));
+        assert_eq!(rational(2.0, 3.0).as_deref(), Some("0.667"));
+        assert_eq!(rational(-2.0, 3.0).as_deref(), Some("-0.667"));
+        assert_eq!(rational(0.0, 3.0).as_deref(), Some("0"));
+        assert_eq!(rational(5.0, 0.0), None);
+    }
+
+    #[test]
+    fn out_of_range_offsets() {
+        let order = Order(false);
+        let tiff = order.tiff(
+            &[
+                (0x0112, 3, 1, order.u16(6)),
+                (0x010f, 2, 6, b"Canon\0".to_vec()),
+            ],
+            None,
+        );
+        let orientation = [("Orientation", "6".to_string())];
+
+        // A value pointing past the end is skipped, the rest stays
+        let value = 8 + 2 + 12 + 8;
+        let mut past = tiff.clone();
+        past[value..value + 4].copy_from_slice(&order.u32(1000));
+        assert_eq!(fields(&past), orientation);
+
+        // An IFD cut short yields the entries that could be read
+        assert_eq!(fields(&tiff[..8 + 2 + 12]), orientation);
+
+        // Nothing is read from an IFD past the end or a broken header
+        let mut past = tiff.clone();
+        past[4..8].copy_from_slice(&order.u32(u32::MAX));
+        assert!(fields(&past).is_empty());
+        assert!(fields(b"II*\0").is_empty());
+        assert!(fields(b"not a tiff").is_empty());
+
+        // So is a sub-IFD past the end
+        let tiff = order.tiff(&[], Some(&[(0x8827, 3, 1, order.u16(100))]));
+        let pointer = 8 + 2 + 8;
+        let mut past = tiff.clone();
+        past[pointer..pointer + 4].copy_from_slice(&order.u32(u32::MAX));
+        assert_eq!(fields(&tiff), [("ISO", "100".to_string())]);
+        assert!(fields(&past).is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+use crate::{animation, exif};
+use anyhow::{anyhow, Result};
+use image::{ExtendedColorType, ImageDecoder, ImageFormat, ImageReader};
+use std::io::Cursor;
+
+/// What is known about an encoded image from its header and metadata
+#[derive(Clone, Debug)]
+pub struct ImageInfo {
+    pub format: Option<ImageFormat>,
+    pub width: u32,
+    pub height: u32,
+    /// Color type as stored in the file
+    pub color_type: ExtendedColorType,
+    /// Bits per channel
+    pub bit_depth: u16,
+    pub file_size: u64,
+    /// Number of animation frames, 1 for still images
+    ///
+    /// The only field that needs the pixels, frames are decoded one at a
+    /// time to count them
+    pub frames: usize,
+    /// Size of the embedded ICC profile in bytes
+    pub icc_profile: Option<usize>,
+    /// Known EXIF fields as (name, value)
+    pub exif: Vec<(&'static str, String)>,
+}
+
+/// Reads the header and metadata of an encoded image
+///
+/// `name` identifies the image in error messages
+pub fn get_info(bytes: &[u8], name: &str) -> Result<ImageInfo> {
+    let decode_error = |_| anyhow!("Failed to decode image: {}", name);
+
+    let reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|_| anyhow!("Failed to read image: {}", name))?;
+    let format = reader.format();
+    let mut decoder = reader.into_decoder().map_err(decode_error)?;
+
+    let (width, height) = decoder.dimensions();
+    let color_type = decoder.original_color_type();
+    // Broken metadata shouldn't hide the rest
+    let icc_profile = decoder.icc_profile().ok().flatten();
+    let exif = decoder
+        .exif_metadata()
+        .ok()
+        .flatten()
+        .map(|chunk| exif::fields(&chunk))
+        .unwrap_or_default();
+    let frames = animation::count_frames(bytes, name).unwrap_or(1);
+
+    Ok(ImageInfo {
+        format,
+        width,
+        height,
+        color_type,
+        bit_depth: color_type.bits_per_pixel() / color_type.channel_count() as u16,
+        file_size: bytes.len() as u64,
+        frames,
+        icc_profile: icc_profile.map(|profile| profile.len()),
+        exif,
+    })
+}
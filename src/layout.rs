@@ -0,0 +1,342 @@
+use crate::backend::Backend;
+use crate::render::{RenderOptions, Renderer};
+use crate::scale::Fit;
+use anyhow::Result;
+use image::{imageops, DynamicImage, RgbaImage};
+use std::io::Write;
+
+/// Empty cells between tiles
+pub const GAP: u32 = 1;
+
+/// An image placed in a grid, with an optional caption underneath
+pub struct Tile {
+    pub image: RgbaImage,
+    pub caption: Option<String>,
+}
+
+/// Draws tiles left to right in rows, every row rendered as one image
+///
+/// Tiles get equal slots aligned to whole cells so captions line up under
+/// them. Slots widen to fit the longest caption, as far as `width` allows,
+/// and longer captions wrap onto more lines. `per_row` defaults to as many
+/// tiles as fit in `width` columns.
+pub fn render_grid(
+    tiles: &[Tile],
+    per_row: Option<usize>,
+    width: u32,
+    (cell_width, cell_height): (u32, u32),
+    backend: &dyn Backend,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let (slot_columns, per_row) = slots(tiles, per_row, width, cell_width);
+    let slot = slot_columns + GAP;
+
+    for (i, row) in tiles.chunks(per_row).enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+
+        let rows = row
+            .iter()
+            .map(|tile| tile.image.height().div_ceil(cell_height))
+            .max()
+            .unwrap_or(1);
+        let mut canvas = RgbaImage::new(
+            (slot * row.len() as u32 - GAP) * cell_width,
+            rows * cell_height,
+        );
+        for (j, tile) in row.iter().enumerate() {
+            let x = j as u32 * slot * cell_width;
+            imageops::replace(&mut canvas, &tile.image, x as i64, 0);
+        }
+
+        backend.render(&canvas, out)?;
+
+        let captions: Vec<_> = row
+            .iter()
+            .map(|tile| wrap(tile.caption.as_deref().unwrap_or_default(), slot_columns))
+            .collect();
+        let lines = captions.iter().map(Vec::len).max().unwrap_or(0);
+        for i in 0..lines {
+            writeln!(out)?;
+            let line: String = captions
+                .iter()
+                .map(|caption| {
+                    let caption = caption.get(i).map_or("", String::as_str);
+                    format!("{:<width$}", caption, width = slot as usize)
+                })
+                .collect();
+            write!(out, "{}", line.trim_end())?;
+        }
+    }
+
+    out.flush()?;
+
+    Ok(())
+}
+
+/// Terminal columns taken up by the widest row of [`render_grid`]
+pub fn grid_columns(
+    tiles: &[Tile],
+    per_row: Option<usize>,
+    width: u32,
+    (cell_width, _): (u32, u32),
+) -> u32 {
+    let (slot_columns, per_row) = slots(tiles, per_row, width, cell_width);
+    let tiles = per_row.min(tiles.len()).max(1) as u32;
+    tiles * (slot_columns + GAP) - GAP
+}
+
+/// Columns of every slot and the number of slots per row
+fn slots(tiles: &[Tile], per_row: Option<usize>, width: u32, cell_width: u32) -> (u32, usize) {
+    let image_columns = tiles
+        .iter()
+        .map(|tile| tile.image.width().div_ceil(cell_width))
+        .max()
+        .unwrap_or(1);
+    let caption_columns = tiles
+        .iter()
+        .filter_map(|tile| tile.caption.as_deref())
+        .map(|caption| caption.chars().count() as u32)
+        .max()
+        .unwrap_or(0);
+    let room = match per_row {
+        Some(per_row) => ((width + GAP) / per_row.max(1) as u32).saturating_sub(GAP),
+        None => width,
+    };
+    let slot_columns = image_columns.max(caption_columns.min(room)).max(1);
+    let per_row = per_row
+        .unwrap_or(((width + GAP) / (slot_columns + GAP)) as usize)
+        .max(1);
+    (slot_columns, per_row)
+}
+
+/// Tiles per row of a paged grid, and rows per page
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pages {
+    pub per_row: u32,
+    /// Rows of tiles on every page, `None` puts all tiles on one page
+    pub rows: Option<u32>,
+}
+
+/// Lays out still images in pages of equally sized slots
+///
+/// Images are scaled to fit their slot in `width` columns. With a number of
+/// rows per page and the terminal `height` in rows, slots are also short
+/// enough for a page to fit on screen.
+pub fn render_pages(
+    images: Vec<(Option<String>, DynamicImage)>,
+    pages: Pages,
+    width: u32,
+    height: Option<u32>,
+    renderer: &Renderer,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let per_row = pages.per_row.max(1);
+    let slot_columns = ((width + GAP) / per_row).saturating_sub(GAP).max(1);
+    // Keep a line free for the prompt and room under every row for captions,
+    // as many lines as the longest one wraps onto
+    let caption_lines = images
+        .iter()
+        .filter_map(|(caption, _)| caption.as_deref())
+        .map(|caption| wrap(caption, slot_columns).len() as u32)
+        .max()
+        .unwrap_or(0);
+    let slot_rows = pages.rows.zip(height).map(|(rows, height)| {
+        (height.saturating_sub(1) / rows.max(1))
+            .saturating_sub(caption_lines)
+            .max(1)
+    });
+
+    let tiler = Renderer::new(RenderOptions {
+        fit: Fit {
+            width: Some(slot_columns),
+            height: slot_rows,
+            ..Fit::default()
+        },
+        ..renderer.options().clone()
+    });
+
+    let tiles = images
+        .into_iter()
+        .map(|(caption, image)| {
+            Ok(Tile {
+                image: tiler.prepare(image)?,
+                caption,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let page = pages
+        .rows
+        .map_or(tiles.len(), |rows| (per_row * rows) as usize);
+    for page in tiles.chunks(page.max(1)) {
+        render_grid(
+            page,
+            Some(per_row as usize),
+            width,
+            tiler.cell_size(),
+            tiler.backend(),
+            out,
+        )?;
+        write!(out, "\n\n")?;
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+/// Breaks `text` into lines of at most `columns` characters, between words
+/// where possible
+fn wrap(text: &str, columns: u32) -> Vec<String> {
+    let columns = columns.max(1) as usize;
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        let length = line.chars().count();
+        if length > 0 && length + 1 + word.len() <= columns {
+            line.push(' ');
+            line.extend(&word);
+            continue;
+        }
+
+        if length > 0 {
+            lines.push(std::mem::take(&mut line));
+        }
+        while word.len() > columns {
+            lines.push(word.drain(..columns).collect());
+        }
+        line.extend(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Charset;
+    use crate::color::ColorDepth;
+
+    /// Writes the size of every image it is given
+    struct Sizes;
+
+    impl Backend for Sizes {
+        fn render(&self, image: &RgbaImage, out: &mut dyn Write) -> Result<()> {
+            write!(out, "[{}x{}]", image.width(), image.height())?;
+            Ok(())
+        }
+    }
+
+    fn tile(width: u32, caption: Option<&str>) -> Tile {
+        Tile {
+            image: RgbaImage::new(width, 2),
+            caption: caption.map(Into::into),
+        }
+    }
+
+    fn grid(tiles: &[Tile], per_row: Option<usize>, width: u32) -> String {
+        let mut out = Vec::new();
+        render_grid(tiles, per_row, width, (1, 2), &Sizes, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn slots_fit_images() {
+        let tiles = [tile(3, None), tile(5, None), tile(2, None)];
+
+        assert_eq!(grid(&tiles, None, 11), "[11x2]\n[5x2]");
+        assert_eq!(grid(&tiles, Some(3), 11), "[17x2]");
+    }
+
+    #[test]
+    fn slots_fit_captions() {
+        let tiles = [tile(4, Some("#1 30ms")), tile(4, Some("#2 30ms"))];
+
+        assert_eq!(grid(&tiles, None, 80), "[15x2]\n#1 30ms #2 30ms");
+        assert_eq!(grid(&tiles, None, 10), "[7x2]\n#1 30ms\n[7x2]\n#2 30ms");
+    }
+
+    #[test]
+    fn grid_width() {
+        let tiles = [tile(3, None), tile(5, None), tile(2, None)];
+
+        assert_eq!(grid_columns(&tiles, None, 11, (1, 2)), 11);
+        assert_eq!(grid_columns(&tiles, None, 80, (1, 2)), 17);
+        assert_eq!(grid_columns(&tiles, Some(2), 80, (1, 2)), 11);
+        assert_eq!(grid_columns(&tiles[..1], None, 80, (1, 2)), 3);
+    }
+
+    #[test]
+    fn long_captions_wrap() {
+        let tiles = [
+            tile(3, Some("a long name")),
+            tile(3, None),
+            tile(3, Some("x")),
+        ];
+
+        assert_eq!(
+            grid(&tiles, Some(3), 17),
+            "[17x2]\na           x\nlong\nname"
+        );
+    }
+
+    fn pages(captions: &[&str], pages: Pages, height: u32) -> Vec<Vec<String>> {
+        let images = captions
+            .iter()
+            .map(|caption| {
+                let image = RgbaImage::from_pixel(10, 100, image::Rgba([255; 4]));
+                (Some(caption.to_string()), image.into())
+            })
+            .collect();
+        let renderer = Renderer::new(RenderOptions {
+            charset: Charset::Ascii,
+            colors: ColorDepth::Mono,
+            ..RenderOptions::default()
+        });
+        let mut out = Vec::new();
+        render_pages(images, pages, 8, Some(height), &renderer, &mut out).unwrap();
+
+        String::from_utf8(out)
+            .unwrap()
+            .split_terminator("\n\n")
+            .map(|page| page.lines().map(str::to_string).collect())
+            .collect()
+    }
+
+    #[test]
+    fn pages_fit_the_terminal() {
+        let page = Pages {
+            per_row: 2,
+            rows: Some(1),
+        };
+
+        // Slots are 3 columns wide, the captions wrap onto two lines
+        let output = pages(&["#1 abc", "#2 abc", "#3 abc"], page, 7);
+        assert_eq!(output.len(), 2);
+        assert!(output.iter().all(|page| page.len() == 6), "{:?}", output);
+        assert_eq!(output[0][4..], ["#1  #2", "abc abc"]);
+        assert_eq!(output[1][4..], ["#3", "abc"]);
+
+        // Without rows per page everything goes on one page of any height
+        let page = Pages {
+            per_row: 2,
+            rows: None,
+        };
+        assert_eq!(pages(&["#1", "#2", "#3"], page, 7).len(), 1);
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(wrap("#1 30ms", 4), ["#1", "30ms"]);
+        assert_eq!(wrap("#1 30ms", 7), ["#1 30ms"]);
+        assert_eq!(wrap("abcdefgh ij", 3), ["abc", "def", "gh", "ij"]);
+        assert_eq!(wrap("  ", 3), Vec::<String>::new());
+        assert_eq!(wrap("ab", 0), ["a", "b"]);
+    }
+}
@@ -0,0 +1,30 @@
+//! Prints images in the terminal
+//!
+//! A [`Renderer`] turns a decoded image into terminal output for one of the
+//! supported protocols and writes it to any [`std::io::Write`], or returns it
+//! as a string with [`Renderer::render_to_string`].
+
+pub mod animation;
+pub mod atlas;
+pub mod backend;
+pub mod caption;
+pub mod color;
+pub mod detect;
+pub mod dither;
+mod exif;
+pub mod info;
+pub mod layout;
+pub mod load;
+pub mod render;
+pub mod scale;
+pub mod transform;
+
+pub use atlas::Atlas;
+pub use backend::{Backend, Charset, Protocol};
+pub use color::ColorDepth;
+pub use dither::Dither;
+pub use info::{get_info, ImageInfo};
+pub use load::{get_format, get_image, get_image_from_bytes};
+pub use render::{Border, Checkerboard, RenderOptions, Renderer};
+pub use scale::{Filter, Fit};
+pub use transform::Transform;
@@ -0,0 +1,265 @@
+use crate::animation::{self, Animation};
+use anyhow::{anyhow, Result};
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use std::io::{self, BufRead, Cursor, Read, Seek};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// A decoded still image or animation
+#[derive(Debug)]
+pub enum Loaded {
+    Still(DynamicImage),
+    Animated(Animation),
+}
+
+impl Loaded {
+    /// Size of the image, or of the first frame of an animation
+    pub fn dimensions(&self) -> (u32, u32) {
+        let image = match self {
+            Self::Still(image) => image,
+            Self::Animated(animation) => &animation.frames[0].image,
+        };
+        (image.width(), image.height())
+    }
+
+    /// The image itself, or the first frame of an animation
+    pub fn into_still(self) -> DynamicImage {
+        match self {
+            Self::Still(image) => image,
+            Self::Animated(mut animation) => animation.frames.swap_remove(0).image,
+        }
+    }
+}
+
+/// Decodes an image, or an animation with `animate`, along with its format
+///
+/// `-` reads standard input
+pub fn load(
+    path: &Path,
+    animate: bool,
+    auto_orient: bool,
+) -> Result<(Loaded, Option<ImageFormat>)> {
+    if path.as_os_str() == "-" {
+        let bytes = read_stdin()?;
+        let format = image::guess_format(&bytes).ok();
+
+        if animate {
+            if let Some(animation) = animation::get_animation_from_bytes(&bytes, "stdin")? {
+                return Ok((Loaded::Animated(animation), format));
+            }
+        }
+        let image = get_image_from_bytes(&bytes, "stdin", auto_orient)?;
+        return Ok((Loaded::Still(image), format));
+    }
+
+    let path = path.to_str();
+    let format = path.and_then(get_format);
+    if let (true, Some(file)) = (animate, path) {
+        if let Some(animation) = animation::get_animation(file)? {
+            return Ok((Loaded::Animated(animation), format));
+        }
+    }
+
+    Ok((Loaded::Still(get_image(path, auto_orient)?), format))
+}
+
+/// Reads a whole file, or standard input for `-`
+pub fn read(path: &Path) -> Result<Vec<u8>> {
+    if path.as_os_str() == "-" {
+        return read_stdin();
+    }
+    std::fs::read(path).map_err(|_| anyhow!("Invalid image path: {}", path.display()))
+}
+
+/// Reads standard input on the first call, later calls get the same bytes so
+/// `-` can be given more than once
+fn read_stdin() -> Result<Vec<u8>> {
+    static STDIN: OnceLock<Option<Vec<u8>>> = OnceLock::new();
+
+    STDIN
+        .get_or_init(|| {
+            let mut bytes = Vec::new();
+            io::stdin().read_to_end(&mut bytes).ok().map(|_| bytes)
+        })
+        .clone()
+        .ok_or_else(|| anyhow!("Failed to read standard input"))
+}
+
+/// Short name of an image for captions and listings, `stdin` for `-`
+pub fn display_name(path: &Path) -> String {
+    if path.as_os_str() == "-" {
+        return "stdin".into();
+    }
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Decodes an image file, sniffing the format from its contents
+///
+/// With `auto_orient` the image is rotated and flipped upright as its EXIF
+/// orientation says
+pub fn get_image(path: Option<&str>, auto_orient: bool) -> Result<DynamicImage> {
+    let Some(file) = path else {
+        return Err(anyhow!("Invalid characters in path"));
+    };
+    let reader = ImageReader::open(file).map_err(|_| anyhow!("Invalid image path: {}", file))?;
+    decode(reader, file, auto_orient)
+}
+
+/// Decodes an image held in memory, sniffing the format from its contents
+///
+/// `name` identifies the image in error messages
+pub fn get_image_from_bytes(bytes: &[u8], name: &str, auto_orient: bool) -> Result<DynamicImage> {
+    decode(ImageReader::new(Cursor::new(bytes)), name, auto_orient)
+}
+
+fn decode<R: BufRead + Seek>(
+    reader: ImageReader<R>,
+    name: &str,
+    auto_orient: bool,
+) -> Result<DynamicImage> {
+    let decode_error = |_| anyhow!("Failed to decode image: {}", name);
+
+    let mut decoder = reader
+        .with_guessed_format()
+        .map_err(|_| anyhow!("Failed to read image: {}", name))?
+        .into_decoder()
+        .map_err(decode_error)?;
+    // A broken orientation tag shouldn't keep the image from showing
+    let orientation = if auto_orient {
+        decoder.orientation().unwrap_or(Orientation::NoTransforms)
+    } else {
+        Orientation::NoTransforms
+    };
+
+    let mut image = DynamicImage::from_decoder(decoder).map_err(decode_error)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+/// Format of an image file judged by its contents, then its extension
+pub fn get_format(file: &str) -> Option<ImageFormat> {
+    ImageReader::open(file)
+        .ok()?
+        .with_guessed_format()
+        .ok()?
+        .format()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{GenericImageView, Rgb, RgbImage};
+
+    const RED: [u8; 3] = [255, 0, 0];
+    const GREEN: [u8; 3] = [0, 255, 0];
+    const BLUE: [u8; 3] = [0, 0, 255];
+
+    /// 32x16 JPEG tagged with `orientation`: red top left quarter, green
+    /// bottom left quarter and a blue right half, as stored
+    fn fixture(orientation: u16) -> Vec<u8> {
+        let image = RgbImage::from_fn(32, 16, |x, y| match (x < 16, y < 8) {
+            (true, true) => Rgb(RED),
+            (true, false) => Rgb(GREEN),
+            (false, _) => Rgb(BLUE),
+        });
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, 100)
+            .encode_image(&image)
+            .unwrap();
+
+        // Big-endian TIFF with a single IFD holding the orientation tag
+        let mut tiff = b"MM\0\x2a\0\0\0\x08\0\x01".to_vec();
+        tiff.extend_from_slice(&[0x01, 0x12, 0, 3, 0, 0, 0, 1]);
+        tiff.extend_from_slice(&orientation.to_be_bytes());
+        tiff.extend_from_slice(&[0; 6]);
+        let app1 = [b"Exif\0\0".as_slice(), &tiff].concat();
+
+        let mut tagged = jpeg[..2].to_vec();
+        tagged.extend_from_slice(&[0xff, 0xe1]);
+        tagged.extend_from_slice(&(app1.len() as u16 + 2).to_be_bytes());
+        tagged.extend_from_slice(&app1);
+        tagged.extend_from_slice(&jpeg[2..]);
+        tagged
+    }
+
+    /// Size and the colors at the centers of the top left, top right,
+    /// bottom left and bottom right quarters
+    fn layout(image: &DynamicImage) -> ((u32, u32), [[u8; 3]; 4]) {
+        let (width, height) = image.dimensions();
+        let quarter = |x, y| {
+            let [r, g, b, _] = image.get_pixel(x * width / 4, y * height / 4).0;
+            // Snap to the closest pure color, JPEG doesn't keep them exact
+            *[RED, GREEN, BLUE]
+                .iter()
+                .min_by_key(|c| {
+                    (0..3)
+                        .map(|i| (c[i] as i32 - [r, g, b][i] as i32).pow(2))
+                        .sum::<i32>()
+                })
+                .unwrap()
+        };
+        (
+            (width, height),
+            [quarter(1, 1), quarter(3, 1), quarter(1, 3), quarter(3, 3)],
+        )
+    }
+
+    #[test]
+    fn orientations() {
+        const STORED: ((u32, u32), [[u8; 3]; 4]) = ((32, 16), [RED, BLUE, GREEN, BLUE]);
+        let upright = [
+            STORED,
+            ((32, 16), [BLUE, RED, BLUE, GREEN]),
+            ((32, 16), [BLUE, GREEN, BLUE, RED]),
+            ((32, 16), [GREEN, BLUE, RED, BLUE]),
+            ((16, 32), [RED, GREEN, BLUE, BLUE]),
+            ((16, 32), [GREEN, RED, BLUE, BLUE]),
+            ((16, 32), [BLUE, BLUE, GREEN, RED]),
+            ((16, 32), [BLUE, BLUE, RED, GREEN]),
+        ];
+
+        for (orientation, expected) in (1..=8).zip(upright) {
+            let jpeg = fixture(orientation);
+            let oriented = get_image_from_bytes(&jpeg, "fixture", true).unwrap();
+            let stored = get_image_from_bytes(&jpeg, "fixture", false).unwrap();
+
+            assert_eq!(layout(&oriented), expected, "orientation {}", orientation);
+            assert_eq!(layout(&stored), STORED, "orientation {}", orientation);
+        }
+    }
+
+    #[test]
+    fn orientation_from_file() {
+        let path = std::env::temp_dir().join(format!("pixprint-orient-{}.jpg", std::process::id()));
+        std::fs::write(&path, fixture(6)).unwrap();
+        let oriented = get_image(path.to_str(), true);
+        let stored = get_image(path.to_str(), false);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            layout(&oriented.unwrap()),
+            ((16, 32), [GREEN, RED, BLUE, BLUE])
+        );
+        assert_eq!(
+            layout(&stored.unwrap()),
+            ((32, 16), [RED, BLUE, GREEN, BLUE])
+        );
+    }
+
+    #[test]
+    fn without_orientation() {
+        let image = RgbImage::from_pixel(3, 2, Rgb(RED));
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+
+        let decoded = get_image_from_bytes(&png, "fixture", true).unwrap();
+        assert_eq!(decoded.dimensions(), (3, 2));
+    }
+}
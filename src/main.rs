@@ -1,22 +1,113 @@
-use anyhow::{anyhow, Result};
-use clap::{command, Parser};
-use hanbun::{Cell, Color};
-use image::imageops::FilterType;
-use image::{DynamicImage, ImageReader};
-use std::path::PathBuf;
+use anyhow::Result;
+use clap::{Parser, Subcommand, ValueEnum};
+use pixprint::animation;
+use pixprint::atlas::Selection;
+use pixprint::caption::{caption, captioned, CaptionPosition};
+use pixprint::color;
+use pixprint::detect::TerminalSize;
+use pixprint::layout::{self, Pages, Tile};
+use pixprint::load::{display_name, load, read, Loaded};
+use pixprint::transform::{Crop, Flip, Gravity, Length, Rotation};
+use pixprint::{
+    detect, get_image_from_bytes, get_info, Atlas, Border, Charset, Checkerboard, ColorDepth,
+    Dither, Filter, Fit, Protocol, RenderOptions, Renderer, Transform,
+};
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+/// Largest size in cells accepted for the output, far beyond any terminal
+const MAX_CELLS: i64 = 1000;
+
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(value_parser = clap::value_parser!(PathBuf))]
+    /// Image files, `-` reads an image from standard input
     images: Vec<PathBuf>,
 
     #[arg(short, long)]
-    #[arg(value_parser = clap::value_parser!(f32))]
+    #[arg(value_parser = parse_scale)]
     /// Scale image by value, values below 1.0 shrink the image
     scale: Option<f32>,
 
+    #[arg(long)]
+    /// Shrink images that don't fit in the terminal window
+    fit: bool,
+
+    #[arg(long, conflicts_with = "scale")]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..=MAX_CELLS))]
+    /// Scale the image to this many columns, keeping its aspect ratio
+    width: Option<u32>,
+
+    #[arg(long, conflicts_with = "scale")]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..=MAX_CELLS))]
+    /// Scale the image to this many rows, keeping its aspect ratio
+    ///
+    /// Together with --width the image fits inside both
+    height: Option<u32>,
+
+    #[arg(long)]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..=MAX_CELLS))]
+    /// Shrink the image to at most this many columns
+    max_width: Option<u32>,
+
+    #[arg(long)]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..=MAX_CELLS))]
+    /// Shrink the image to at most this many rows
+    max_height: Option<u32>,
+
+    #[arg(long, value_name = "N")]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+    /// Have the terminal stretch the image over N columns, kitty and iterm only
+    place_columns: Option<u32>,
+
+    #[arg(long, value_name = "N")]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+    /// Have the terminal stretch the image over N rows, kitty and iterm only
+    ///
+    /// Without --place-columns the terminal keeps the aspect ratio
+    place_rows: Option<u32>,
+
+    #[arg(long)]
+    /// Show images as stored instead of turning them upright by their EXIF orientation
+    no_auto_orient: bool,
+
+    #[arg(long, value_name = "REGION")]
+    #[arg(value_parser = parse_crop)]
+    /// Only show part of the image, as X,Y,W,H or WxH@GRAVITY
+    ///
+    /// Sizes and positions are pixels or percentages of the image, like
+    /// 10,10,50%,50% or 64x64@center. Gravity is one of center, top, bottom,
+    /// left, right, top-left, top-right, bottom-left or bottom-right. Regions
+    /// are cut at the edges of the image, but must start inside it.
+    crop: Option<Crop>,
+
+    #[arg(long, value_name = "DEGREES")]
+    #[arg(value_enum)]
+    /// Rotate the image clockwise, after cropping
+    rotate: Option<Rotation>,
+
+    #[arg(long)]
+    #[arg(value_enum)]
+    /// Mirror the image, after cropping and rotating
+    flip: Option<Flip>,
+
+    #[arg(long)]
+    #[arg(value_enum, default_value_t)]
+    /// Resampling filter used when scaling
+    filter: Filter,
+
+    #[arg(long)]
+    /// Only scale by whole multiples using nearest neighbour, keeps pixel art crisp
+    ///
+    /// Shrinking divides by a whole number instead
+    integer_scale: bool,
+
     #[arg(short, long)]
     #[arg(value_parser = parse_padding)]
     /// Add padding around the image
@@ -24,27 +115,413 @@ struct Cli {
     /// Can be one value for all sides or up to
     /// 4 values following CSS padding rules
     padding: Option<(u32, u32, u32, u32)>,
+
+    #[arg(long, value_name = "COLOR", requires = "padding")]
+    #[arg(value_parser = color::parse_hex)]
+    /// Fill the padding with this color, `#rrggbb`
+    padding_color: Option<[u8; 3]>,
+
+    #[arg(long, conflicts_with_all = ["grid", "columns", "sheet", "tile"])]
+    #[arg(value_enum)]
+    /// Draw a box around each image, not available in grids
+    frame: Option<Border>,
+
+    #[arg(long, value_name = "COLOR")]
+    #[arg(value_parser = parse_background)]
+    /// Blend translucent pixels onto this color, `#rrggbb` or `auto`
+    ///
+    /// `auto` asks the terminal for its background color
+    background: Option<Background>,
+
+    #[arg(long, conflicts_with = "background")]
+    /// Show transparent areas over a light and dark checker pattern
+    checkerboard: bool,
+
+    #[arg(long, value_name = "ROWS", requires = "checkerboard")]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+    /// Height of the checker squares in terminal rows [default: 1]
+    checker_size: Option<u32>,
+
+    #[arg(long, value_name = "LIGHT,DARK", requires = "checkerboard")]
+    #[arg(value_parser = parse_checker_colors)]
+    /// Colors of the checker squares [default: #ccc,#999]
+    checker_colors: Option<([u8; 3], [u8; 3])>,
+
+    #[arg(long, value_name = "ALPHA")]
+    /// Leave pixels with less alpha transparent and draw the rest opaque
+    ///
+    /// Defaults to 1 with --background and 0 with --checkerboard, without
+    /// them only fully opaque pixels are drawn
+    alpha_threshold: Option<u8>,
+
+    #[arg(short, long)]
+    #[arg(value_enum)]
+    /// Colors available in the terminal
+    ///
+    /// Detected from COLORTERM and TERM when not given. The ascii charset
+    /// defaults to mono, plain text without escapes, when output is piped.
+    colors: Option<ColorDepth>,
+
+    #[arg(short, long)]
+    #[arg(value_enum, default_value_t)]
+    /// Dithering used when the terminal has a limited palette
+    dither: Dither,
+
+    #[arg(long)]
+    #[arg(value_enum)]
+    /// How the image is sent to the terminal
+    ///
+    /// Detected by querying the terminal when not given
+    protocol: Option<Protocol>,
+
+    #[arg(long)]
+    #[arg(value_enum, default_value_t)]
+    /// Characters used by the blocks protocol
+    charset: Charset,
+
+    #[arg(long, default_value = " .:-=+*#%@")]
+    #[arg(value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    /// Characters from darkest to brightest used by the ascii charset
+    ///
+    /// Add --colors mono for plain text that survives escape stripping
+    ramp: String,
+
+    #[arg(long = "loop", value_name = "TIMES", require_equals = true)]
+    #[arg(num_args = 0..=1, default_missing_value = "0")]
+    /// Play animations this many times, 0 or no value repeats forever
+    ///
+    /// Defaults to the loop count stored in the file
+    loops: Option<u32>,
+
+    #[arg(long)]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+    /// Only play the first N frames of animations
+    frames: Option<u32>,
+
+    #[arg(long)]
+    /// Lay out all frames of animations in a grid instead of playing them
+    ///
+    /// Every frame is labelled with its number and delay
+    sheet: bool,
+
+    #[arg(long, value_name = "COLSxROWS", conflicts_with = "columns")]
+    #[arg(value_parser = parse_size)]
+    /// Place images side by side, COLS per row and ROWS rows per screen
+    ///
+    /// Tiles are scaled to fit an equal share of the terminal
+    grid: Option<(u32, u32)>,
+
+    #[arg(long, value_name = "N")]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+    /// Place images side by side, N per row, scaled to an equal share of the width
+    columns: Option<u32>,
+
+    #[arg(long, value_name = "WxH", conflicts_with_all = ["grid", "columns", "sheet"])]
+    #[arg(value_parser = parse_size)]
+    /// Slice images into tiles of W by H pixels and show them in a labelled grid
+    ///
+    /// Crop, rotation and flip apply to the whole image before it is sliced
+    tile: Option<(u32, u32)>,
+
+    #[arg(long, value_name = "PIXELS", default_value_t = 0, requires = "tile")]
+    /// Pixels before the first tile at the top and left edge of the atlas
+    tile_margin: u32,
+
+    #[arg(long, value_name = "PIXELS", default_value_t = 0, requires = "tile")]
+    /// Pixels between neighbouring tiles
+    tile_spacing: u32,
+
+    #[arg(long, value_name = "INDICES", requires = "tile")]
+    #[arg(value_parser = parse_selection)]
+    /// Only show these tiles, like 0,4,8-11, counted left to right from the top left
+    tiles: Option<Selection>,
+
+    #[arg(long, value_name = "TEMPLATE", require_equals = true)]
+    #[arg(num_args = 0..=1, default_missing_value = "{name}")]
+    /// Print a caption with every image, the file name by default
+    ///
+    /// The template can use {name}, {path}, {width}, {height} and {format}.
+    /// Captions are cut to the width of the image.
+    caption: Option<String>,
+
+    #[arg(long, value_name = "POSITION", requires = "caption")]
+    #[arg(value_enum, default_value_t)]
+    /// Where captions go, grids always put them below the tiles
+    caption_position: CaptionPosition,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the format, dimensions and EXIF fields of images
+    Info {
+        #[arg(value_parser = clap::value_parser!(PathBuf))]
+        /// Image files, `-` reads an image from standard input
+        images: Vec<PathBuf>,
+
+        #[arg(long)]
+        /// Also print a small preview of every image
+        thumbnail: bool,
+    },
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    let terminal = detect::terminal_size();
+
+    if let Some(Command::Info { images, thumbnail }) = &cli.command {
+        let thumbnail = thumbnail.then(|| renderer(&cli, terminal));
+        return print_info(images, thumbnail);
+    }
+
+    let renderer = renderer(&cli, terminal);
+    let cell_size = renderer.cell_size();
+
+    let montage = cli.grid.is_some() || cli.columns.is_some();
+    let atlas = cli.tile.map(|(tile_width, tile_height)| Atlas {
+        tile_width,
+        tile_height,
+        margin: cli.tile_margin,
+        spacing: cli.tile_spacing,
+    });
+    // Animations need a terminal to redraw in, elsewhere the first frame is printed
+    let animate = !montage && atlas.is_none() && (cli.sheet || io::stdout().is_terminal());
+    let width = terminal.map_or(80, |t| t.columns);
 
     let (images, errors): (Vec<_>, Vec<_>) = cli
         .images
         .iter()
-        .map(|v| get_image(v.to_str()))
+        .map(|v| {
+            load(v, animate, !cli.no_auto_orient).map(|(image, format)| {
+                let caption = cli
+                    .caption
+                    .as_deref()
+                    .map(|template| caption(template, v, format, &image));
+                (caption, image)
+            })
+        })
         .partition(Result::is_ok);
 
     let images: Vec<_> = images.into_iter().map(Result::unwrap).collect();
-    let errors: Vec<_> = errors.into_iter().map(Result::unwrap_err).collect();
+    let mut errors: Vec<_> = errors.into_iter().map(Result::unwrap_err).collect();
+
+    if montage {
+        let pages = Pages {
+            per_row: cli
+                .grid
+                .map_or(cli.columns.unwrap_or(1), |(columns, _)| columns),
+            rows: cli.grid.map(|(_, rows)| rows),
+        };
+        let images = images
+            .into_iter()
+            .map(|(caption, image)| (caption, image.into_still()))
+            .collect();
+        let height = terminal.map(|t| t.rows);
+        let mut out = io::stdout().lock();
+        layout::render_pages(images, pages, width, height, &renderer, &mut out)?;
+    } else {
+        for (caption, image) in images {
+            let position = cli.caption_position;
+
+            if let Some(atlas) = atlas {
+                let tiles = match atlas.slice(&renderer, image.into_still(), cli.tiles.as_ref()) {
+                    Ok(tiles) => tiles,
+                    Err(error) => {
+                        errors.push(error);
+                        continue;
+                    }
+                };
+                let columns = layout::grid_columns(&tiles, None, width, cell_size);
+                captioned(
+                    caption,
+                    position,
+                    columns,
+                    &mut io::stdout().lock(),
+                    |out| {
+                        let backend = renderer.backend();
+                        layout::render_grid(&tiles, None, width, cell_size, backend, out)
+                    },
+                )?;
+                println!("\n");
+                continue;
+            }
+
+            match image {
+                Loaded::Still(image) => {
+                    let image = match renderer.prepare(image) {
+                        Ok(image) => image,
+                        Err(error) => {
+                            errors.push(error);
+                            continue;
+                        }
+                    };
+                    let columns = renderer.columns(&image);
+                    captioned(
+                        caption,
+                        position,
+                        columns,
+                        &mut io::stdout().lock(),
+                        |out| renderer.draw(&image, out),
+                    )?;
+                }
+                Loaded::Animated(mut animation) => {
+                    if let Some(count) = cli.frames {
+                        animation.frames.truncate(count as usize);
+                    }
+
+                    let prepared = animation
+                        .frames
+                        .into_iter()
+                        .map(|frame| Ok((renderer.prepare(frame.image)?, frame.delay)))
+                        .collect::<Result<Vec<_>>>();
+                    let prepared = match prepared {
+                        Ok(prepared) => prepared,
+                        Err(error) => {
+                            errors.push(error);
+                            continue;
+                        }
+                    };
 
-    for mut image in images {
-        if let Some(scale_factor) = cli.scale {
-            image = scale_image(image, scale_factor);
+                    if cli.sheet {
+                        let tiles: Vec<_> = prepared
+                            .into_iter()
+                            .enumerate()
+                            .map(|(i, (image, delay))| Tile {
+                                image,
+                                caption: Some(format!("#{} {}ms", i + 1, delay.as_millis())),
+                            })
+                            .collect();
+
+                        let columns = layout::grid_columns(&tiles, None, width, cell_size);
+                        captioned(
+                            caption,
+                            position,
+                            columns,
+                            &mut io::stdout().lock(),
+                            |out| {
+                                let backend = renderer.backend();
+                                layout::render_grid(&tiles, None, width, cell_size, backend, out)
+                            },
+                        )?;
+                    } else {
+                        let columns = renderer.columns(&prepared[0].0);
+                        let mut rows = 0;
+                        let mut frames = Vec::new();
+                        for (index, (image, delay)) in prepared.into_iter().enumerate() {
+                            rows = renderer.rows(&image);
+
+                            let mut output = Vec::new();
+                            renderer.draw_for_playback(&image, index, &mut output)?;
+                            frames.push((output, delay));
+                        }
+
+                        let loops = match cli.loops {
+                            Some(0) => None,
+                            Some(count) => Some(count),
+                            None => animation.loops,
+                        };
+
+                        captioned(
+                            caption,
+                            position,
+                            columns,
+                            &mut io::stdout().lock(),
+                            |out| {
+                                if !animation::play(&frames, rows, loops, out)? {
+                                    std::process::exit(130);
+                                }
+                                Ok(())
+                            },
+                        )?;
+                    }
+                }
+            }
+
+            println!("\n");
         }
+    }
+
+    for error in errors {
+        println!("{}", error);
+    }
+
+    Ok(())
+}
+
+/// Builds the renderer for the drawing options on the command line
+fn renderer(cli: &Cli, terminal: Option<TerminalSize>) -> Renderer {
+    let mut fit = Fit {
+        width: cli.width,
+        height: cli.height,
+        max_width: cli.max_width,
+        max_height: cli.max_height,
+    };
+    if let (true, Some(terminal)) = (cli.fit, terminal) {
+        fit = fit.within(terminal);
+    }
+
+    Renderer::new(RenderOptions {
+        protocol: cli.protocol.unwrap_or_else(detect::protocol),
+        colors: cli.colors.unwrap_or_else(|| {
+            // Ascii art is mostly pasted somewhere that shows escapes verbatim
+            if cli.charset == Charset::Ascii && !io::stdout().is_terminal() {
+                ColorDepth::Mono
+            } else {
+                ColorDepth::detect()
+            }
+        }),
+        dither: cli.dither,
+        charset: cli.charset,
+        ramp: cli.ramp.clone(),
+        transform: Transform {
+            crop: cli.crop,
+            rotate: cli.rotate,
+            flip: cli.flip,
+        },
+        scale: cli.scale,
+        fit,
+        filter: cli.filter,
+        integer_scale: cli.integer_scale,
+        columns: cli.place_columns,
+        rows: cli.place_rows,
+        padding: cli.padding,
+        padding_color: cli.padding_color,
+        border: cli.frame,
+        background: cli.background.and_then(|background| match background {
+            Background::Auto => detect::background(),
+            Background::Color(color) => Some(color),
+        }),
+        checkerboard: cli.checkerboard.then(|| {
+            let default = Checkerboard::default();
+            Checkerboard {
+                size: cli.checker_size.unwrap_or(default.size),
+                colors: cli.checker_colors.unwrap_or(default.colors),
+            }
+        }),
+        alpha_threshold: cli.alpha_threshold,
+        terminal,
+    })
+}
+
+/// Prints what is known about every image, optionally above a thumbnail
+fn print_info(images: &[PathBuf], thumbnail: Option<Renderer>) -> Result<()> {
+    const THUMBNAIL_SIZE: (u32, u32) = (32, 12);
+
+    let thumbnail = thumbnail.map(|renderer| {
+        Renderer::new(RenderOptions {
+            fit: Fit {
+                max_width: Some(THUMBNAIL_SIZE.0),
+                max_height: Some(THUMBNAIL_SIZE.1),
+                ..Fit::default()
+            },
+            ..renderer.options().clone()
+        })
+    });
 
-        draw(image, cli.padding)?;
-        println!("\n");
+    let mut errors = Vec::new();
+    for path in images {
+        if let Err(error) = print_image_info(path, thumbnail.as_ref()) {
+            errors.push(error);
+        }
     }
 
     for error in errors {
@@ -54,6 +531,175 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+fn print_image_info(path: &Path, thumbnail: Option<&Renderer>) -> Result<()> {
+    let name = display_name(path);
+    let bytes = read(path)?;
+    let info = get_info(&bytes, &name)?;
+
+    if let Some(thumbnail) = thumbnail {
+        let image = get_image_from_bytes(&bytes, &name, true)?;
+        thumbnail.render(image, &mut io::stdout().lock())?;
+        println!();
+    }
+
+    let format = info
+        .format
+        .map_or("unknown".into(), |f| format!("{:?}", f).to_uppercase());
+    let icc_profile = info
+        .icc_profile
+        .map_or("none".into(), |size| format!("{} bytes", size));
+    let mut fields = vec![
+        ("Format", format),
+        ("Dimensions", format!("{}x{}", info.width, info.height)),
+        ("Color type", format!("{:?}", info.color_type)),
+        ("Bit depth", info.bit_depth.to_string()),
+        ("File size", file_size(info.file_size)),
+        ("Frames", info.frames.to_string()),
+        ("ICC profile", icc_profile),
+    ];
+    fields.extend(info.exif);
+
+    let width = fields
+        .iter()
+        .map(|(label, _)| label.len())
+        .max()
+        .unwrap_or(0);
+    if path.as_os_str() == "-" {
+        println!("{}", name);
+    } else {
+        println!("{}", path.display());
+    }
+    for (label, value) in fields {
+        println!("  {:<width$}  {}", label, value);
+    }
+    println!();
+
+    Ok(())
+}
+
+/// Size in bytes with a binary unit
+fn file_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["bytes", "KiB", "MiB", "GiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} bytes", bytes)
+    } else {
+        format!("{:.1} {} ({} bytes)", size, UNITS[unit], bytes)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Background {
+    Auto,
+    Color([u8; 3]),
+}
+
+fn parse_background(background: &str) -> Result<Background, String> {
+    if background == "auto" {
+        Ok(Background::Auto)
+    } else {
+        color::parse_hex(background).map(Background::Color)
+    }
+}
+
+fn parse_checker_colors(colors: &str) -> Result<([u8; 3], [u8; 3]), String> {
+    let (light, dark) = colors
+        .split_once(',')
+        .ok_or_else(|| format!("Expected two colors separated by a comma: {}", colors))?;
+    Ok((
+        color::parse_hex(light.trim())?,
+        color::parse_hex(dark.trim())?,
+    ))
+}
+
+fn parse_scale(scale: &str) -> Result<f32, String> {
+    f32::from_str(scale.trim())
+        .ok()
+        .filter(|scale| scale.is_finite() && *scale > 0.0)
+        .ok_or_else(|| format!("Expected a number above 0: {}", scale))
+}
+
+/// Parses two positive numbers like `4x3`
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("Expected two numbers like 4x3: {}", size);
+    let (width, height) = size.split_once(['x', 'X']).ok_or_else(invalid)?;
+    let width = u32::from_str(width.trim()).map_err(|_| invalid())?;
+    let height = u32::from_str(height.trim()).map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok((width, height))
+}
+
+fn parse_selection(selection: &str) -> Result<Selection, String> {
+    let invalid = || format!("Expected indices and ranges like 0,4,8-11: {}", selection);
+    let index = |index: &str| u32::from_str(index.trim()).map_err(|_| invalid());
+
+    selection
+        .split(',')
+        .map(|part| match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (index(start)?, index(end)?);
+                if start > end {
+                    return Err(format!("Range {}-{} runs backwards", start, end));
+                }
+                Ok(start..=end)
+            }
+            None => index(part).map(|i| i..=i),
+        })
+        .collect::<Result<_, _>>()
+        .map(Selection)
+}
+
+fn parse_crop(crop: &str) -> Result<Crop, String> {
+    let invalid = || format!("Expected X,Y,W,H or WxH@GRAVITY: {}", crop);
+
+    if let Some((size, gravity)) = crop.split_once('@') {
+        let (width, height) = size.split_once(['x', 'X']).ok_or_else(invalid)?;
+        let gravity = <Gravity as ValueEnum>::from_str(gravity.trim(), true)
+            .map_err(|_| format!("Invalid gravity: {}", gravity))?;
+        return Ok(Crop::Gravity {
+            width: parse_length(width)?,
+            height: parse_length(height)?,
+            gravity,
+        });
+    }
+
+    let values: Vec<_> = crop
+        .split(',')
+        .map(parse_length)
+        .collect::<Result<_, _>>()?;
+    let [x, y, width, height] = values[..] else {
+        return Err(invalid());
+    };
+    Ok(Crop::At {
+        x,
+        y,
+        width,
+        height,
+    })
+}
+
+/// Parses pixels like `64` or a percentage like `50%`
+fn parse_length(length: &str) -> Result<Length, String> {
+    let length = length.trim();
+    let parsed = match length.strip_suffix('%') {
+        Some(percent) => f32::from_str(percent)
+            .ok()
+            .filter(|percent| (0.0..=100.0).contains(percent))
+            .map(Length::Percent),
+        None => u32::from_str(length).ok().map(Length::Pixels),
+    };
+    parsed.ok_or_else(|| format!("Invalid length: {}", length))
+}
+
 fn parse_padding(padding: &str) -> Result<(u32, u32, u32, u32), String> {
     let parts: Vec<_> = padding.split_whitespace().collect();
 
@@ -81,77 +727,129 @@ fn parse_padding(padding: &str) -> Result<(u32, u32, u32, u32), String> {
     }
 }
 
-fn get_image(path: Option<&str>) -> Result<DynamicImage> {
-    let Some(file) = path else {
-        return Err(anyhow!("Invalid characters in path"));
-    };
-    ImageReader::open(file)
-        .map_err(|_| anyhow!("Invalid image path: {}", file))?
-        .decode()
-        .map_err(|_| anyhow!("Failed to decode image: {}", file))
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn scale_image(image: DynamicImage, scale: f32) -> DynamicImage {
-    let nwidth = image.width() as f32 * scale;
-    let nheight = image.height() as f32 * scale;
+    #[test]
+    fn scale() {
+        assert_eq!(parse_scale("0.5"), Ok(0.5));
+        assert_eq!(parse_scale("2"), Ok(2.0));
+        assert!(parse_scale("0").is_err());
+        assert!(parse_scale("-1").is_err());
+        assert!(parse_scale("inf").is_err());
+        assert!(parse_scale("NaN").is_err());
+        assert!(Cli::try_parse_from(["pixprint", "--scale=-1", "--integer-scale"]).is_err());
+    }
 
-    image.resize(nwidth as u32, nheight as u32, FilterType::CatmullRom)
-}
+    #[test]
+    fn frame_outside_grids() {
+        let parse = |args: &[&str]| Cli::try_parse_from([&["pixprint", "a.png"], args].concat());
 
-fn draw(img: DynamicImage, padding: Option<(u32, u32, u32, u32)>) -> Result<()> {
-    let (mut width, mut height) = (img.width(), img.height());
-    if let Some((top, right, bottom, left)) = padding {
-        width += left + right;
-        height += top + bottom;
+        assert!(parse(&["--frame", "solid"]).is_ok());
+        assert!(parse(&["--frame", "solid", "--columns", "2"]).is_err());
+        assert!(parse(&["--frame", "solid", "--grid", "2x2"]).is_err());
+        assert!(parse(&["--frame", "solid", "--sheet"]).is_err());
+        assert!(parse(&["--frame", "solid", "--tile", "4x4"]).is_err());
     }
-    let mut buffer = hanbun::Buffer::new(width as usize, height as usize / 2, ' ');
 
-    let rgb = img.into_rgba8();
+    #[test]
+    fn selection() {
+        let ranges = |selection| parse_selection(selection).map(|s| s.0);
 
-    for y in 0..height / 2 {
-        for x in 0..width {
-            let (top_pixel, bot_pixel) = match padding {
-                Some((top, right, bottom, left)) => {
-                    if y * 2 < top || y * 2 >= height - bottom || x < left || x >= width - right {
-                        (None, None)
-                    } else {
-                        (
-                            rgb.get_pixel_checked(x - left, y * 2 - top),
-                            rgb.get_pixel_checked(x - left, (y * 2) + 1 - top),
-                        )
-                    }
-                }
-                None => (
-                    rgb.get_pixel_checked(x, y * 2),
-                    rgb.get_pixel_checked(x, (y * 2) + 1),
-                ),
-            };
-
-            buffer.cells[((y * width) + x) as usize] = Cell {
-                char: Some(' '),
-                char_color: None,
-                upper_block: pixel_to_cell_color(top_pixel),
-                lower_block: pixel_to_cell_color(bot_pixel),
-            };
-        }
+        assert_eq!(ranges("3"), Ok(vec![3..=3]));
+        assert_eq!(ranges("0,4, 8-11"), Ok(vec![0..=0, 4..=4, 8..=11]));
+        assert_eq!(ranges("2-2"), Ok(vec![2..=2]));
+        assert!(ranges("5-2").is_err());
+        assert!(ranges("1-").is_err());
+        assert!(ranges("a").is_err());
+        assert!(ranges("").is_err());
     }
 
-    buffer.draw();
+    #[test]
+    fn lengths() {
+        assert_eq!(parse_length("64"), Ok(Length::Pixels(64)));
+        assert_eq!(parse_length(" 0 "), Ok(Length::Pixels(0)));
+        assert_eq!(parse_length("50%"), Ok(Length::Percent(50.0)));
+        assert_eq!(parse_length("12.5%"), Ok(Length::Percent(12.5)));
+        assert_eq!(parse_length("100%"), Ok(Length::Percent(100.0)));
+        assert!(parse_length("101%").is_err());
+        assert!(parse_length("-5%").is_err());
+        assert!(parse_length("-5").is_err());
+        assert!(parse_length("%").is_err());
+        assert!(parse_length("5px").is_err());
+    }
 
-    Ok(())
-}
+    #[test]
+    fn crops() {
+        assert_eq!(
+            parse_crop("10,20%,30,40"),
+            Ok(Crop::At {
+                x: Length::Pixels(10),
+                y: Length::Percent(20.0),
+                width: Length::Pixels(30),
+                height: Length::Pixels(40),
+            })
+        );
+        assert_eq!(
+            parse_crop("50%x64@bottom-right"),
+            Ok(Crop::Gravity {
+                width: Length::Percent(50.0),
+                height: Length::Pixels(64),
+                gravity: Gravity::BottomRight,
+            })
+        );
 
-fn pixel_to_cell_color(pixel_opt: Option<&image::Rgba<u8>>) -> Option<Option<Color>> {
-    pixel_opt.and_then(|p| {
-        let alpha = p.0[3];
-        if alpha == 255 {
-            Some(Some(Color::Rgb {
-                r: p.0[0],
-                g: p.0[1],
-                b: p.0[2],
-            }))
-        } else {
-            None
+        let gravities = [
+            ("top-left", Gravity::TopLeft),
+            ("top", Gravity::Top),
+            ("top-right", Gravity::TopRight),
+            ("left", Gravity::Left),
+            ("center", Gravity::Center),
+            ("right", Gravity::Right),
+            ("bottom-left", Gravity::BottomLeft),
+            ("bottom", Gravity::Bottom),
+            ("BOTTOM-RIGHT", Gravity::BottomRight),
+        ];
+        for (name, gravity) in gravities {
+            let crop = parse_crop(&format!("4X4@{}", name));
+            assert!(
+                matches!(crop, Ok(Crop::Gravity { gravity: g, .. }) if g == gravity),
+                "{}",
+                name
+            );
         }
-    })
+
+        assert!(parse_crop("1,2,3").is_err());
+        assert!(parse_crop("1,2,3,4,5").is_err());
+        assert!(parse_crop("4x4@middle").is_err());
+        assert!(parse_crop("4@center").is_err());
+        assert!(parse_crop("4x4x4@center").is_err());
+    }
+
+    #[test]
+    fn size_limits() {
+        let parse =
+            |flag: &str, value: &str| Cli::try_parse_from(["pixprint", flag, value, "a.png"]);
+
+        for flag in ["--width", "--height", "--max-width", "--max-height"] {
+            assert!(parse(flag, "1000").is_ok(), "{}", flag);
+            assert!(parse(flag, "1001").is_err(), "{}", flag);
+            assert!(parse(flag, "4294967295").is_err(), "{}", flag);
+            assert!(parse(flag, "0").is_err(), "{}", flag);
+        }
+    }
+
+    #[test]
+    fn loops() {
+        let cli = Cli::try_parse_from(["pixprint", "--loop", "anim.gif"]).unwrap();
+        assert_eq!(cli.loops, Some(0));
+        assert_eq!(cli.images, [PathBuf::from("anim.gif")]);
+
+        let cli = Cli::try_parse_from(["pixprint", "--loop=3", "anim.gif"]).unwrap();
+        assert_eq!(cli.loops, Some(3));
+
+        let cli = Cli::try_parse_from(["pixprint", "anim.gif"]).unwrap();
+        assert_eq!(cli.loops, None);
+    }
 }
@@ -0,0 +1,781 @@
+use crate::backend::{Backend, Charset, Protocol};
+use crate::color::ColorDepth;
+use crate::detect::{self, TerminalSize};
+use crate::dither::Dither;
+use crate::scale::{self, Filter, Fit};
+use crate::transform::Transform;
+use anyhow::Result;
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use std::io::Write;
+
+/// How images are scaled and drawn
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    pub protocol: Protocol,
+    pub colors: ColorDepth,
+    pub dither: Dither,
+    /// Characters used by the blocks protocol
+    pub charset: Charset,
+    /// Characters from darkest to brightest used by the ascii charset
+    pub ramp: String,
+    /// Crop, rotation and flip applied before scaling
+    pub transform: Transform,
+    /// Scale factor applied before the size constraints
+    pub scale: Option<f32>,
+    pub fit: Fit,
+    pub filter: Filter,
+    /// Only scale by whole multiples or divisors
+    pub integer_scale: bool,
+    /// Columns the kitty and iterm protocols stretch the image over, its own width when `None`
+    pub columns: Option<u32>,
+    /// Rows the kitty and iterm protocols stretch the image over, its own height when `None`
+    pub rows: Option<u32>,
+    /// Pixels around the image as (top, right, bottom, left)
+    pub padding: Option<(u32, u32, u32, u32)>,
+    /// Fill for the padding, transparent when `None`
+    pub padding_color: Option<[u8; 3]>,
+    /// Box drawn around the image
+    pub border: Option<Border>,
+    /// Color translucent pixels are blended onto
+    pub background: Option<[u8; 3]>,
+    /// Pattern translucent pixels are blended onto, takes precedence over `background`
+    pub checkerboard: Option<Checkerboard>,
+    /// Pixels with less alpha are left transparent, the rest are drawn opaque
+    ///
+    /// Defaults to 1 with a background and 0 with a checkerboard. Without
+    /// any of them, pixels are passed on as they are and backends without
+    /// alpha support only draw opaque ones.
+    pub alpha_threshold: Option<u8>,
+    /// Window the output is meant for, used for the cell size of pixel protocols
+    pub terminal: Option<TerminalSize>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            protocol: Protocol::Blocks,
+            colors: ColorDepth::Truecolor,
+            dither: Dither::default(),
+            charset: Charset::default(),
+            ramp: " .:-=+*#%@".into(),
+            transform: Transform::default(),
+            scale: None,
+            fit: Fit::default(),
+            filter: Filter::default(),
+            integer_scale: false,
+            columns: None,
+            rows: None,
+            padding: None,
+            padding_color: None,
+            border: None,
+            background: None,
+            checkerboard: None,
+            alpha_threshold: None,
+            terminal: None,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Defaults with the protocol, colors and size of the terminal on stdout
+    pub fn detect() -> Self {
+        Self {
+            protocol: detect::protocol(),
+            colors: ColorDepth::detect(),
+            terminal: detect::terminal_size(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Box-drawing style of the frame around an image
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Border {
+    /// Single lines with square corners
+    Solid,
+    /// Double lines
+    Double,
+    /// Single lines with rounded corners
+    Rounded,
+}
+
+impl Border {
+    /// Corners clockwise from the top left, then the horizontal and vertical lines
+    fn chars(self) -> [char; 6] {
+        match self {
+            Self::Solid => ['┌', '┐', '┘', '└', '─', '│'],
+            Self::Double => ['╔', '╗', '╝', '╚', '═', '║'],
+            Self::Rounded => ['╭', '╮', '╯', '╰', '─', '│'],
+        }
+    }
+}
+
+/// Light and dark squares shown behind transparent pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checkerboard {
+    /// Side of a square in terminal rows
+    pub size: u32,
+    /// Light and dark square colors
+    pub colors: ([u8; 3], [u8; 3]),
+}
+
+impl Default for Checkerboard {
+    fn default() -> Self {
+        Self {
+            size: 1,
+            colors: ([0xcc; 3], [0x99; 3]),
+        }
+    }
+}
+
+impl Checkerboard {
+    /// Color at pixel `x`, `y` with squares of `size` pixels
+    fn color(&self, x: u32, y: u32, size: u32) -> [u8; 3] {
+        let size = size.max(1);
+        if (x / size + y / size).is_multiple_of(2) {
+            self.colors.0
+        } else {
+            self.colors.1
+        }
+    }
+}
+
+/// Draws images with a fixed set of options
+pub struct Renderer {
+    options: RenderOptions,
+    backend: Box<dyn Backend>,
+}
+
+impl Renderer {
+    pub fn new(options: RenderOptions) -> Self {
+        let backend = options.protocol.backend(
+            options.colors,
+            options.dither,
+            options.charset,
+            &options.ramp,
+            options.columns,
+            options.rows,
+        );
+        Self { options, backend }
+    }
+
+    pub fn options(&self) -> &RenderOptions {
+        &self.options
+    }
+
+    /// The backend drawing prepared images
+    pub fn backend(&self) -> &dyn Backend {
+        &*self.backend
+    }
+
+    /// Image pixels covered by one terminal cell as (width, height)
+    pub fn cell_size(&self) -> (u32, u32) {
+        self.options
+            .protocol
+            .cell_size(self.options.charset, self.options.terminal)
+    }
+
+    /// Transforms, scales and pads an image into the pixels handed to the backend
+    ///
+    /// Fails when the crop region starts outside the image
+    pub fn prepare(&self, image: DynamicImage) -> Result<RgbaImage> {
+        let options = &self.options;
+        let mut image = options.transform.apply(image)?;
+        let mut factor = options.scale.unwrap_or(1.0);
+        factor *= options.fit.factor(
+            ((image.width() as f32 * factor) as u32).max(1),
+            ((image.height() as f32 * factor) as u32).max(1),
+            self.cell_size(),
+            options.padding,
+            self.frame(),
+        );
+
+        if options.integer_scale {
+            image = scale::scale_image_integer(image, factor);
+        } else if factor != 1.0 {
+            image = scale::scale_image(image, factor, options.filter.into());
+        }
+
+        let mut rgba = image.into_rgba8();
+        if let Some(checkerboard) = options.checkerboard {
+            let size = checkerboard.size * self.cell_size().1;
+            let threshold = options.alpha_threshold.unwrap_or(0);
+            composite(
+                &mut rgba,
+                |x, y| Some(checkerboard.color(x, y, size)),
+                threshold,
+            );
+        } else if options.background.is_some() || options.alpha_threshold.is_some() {
+            let threshold = options.alpha_threshold.unwrap_or(1);
+            composite(&mut rgba, |_, _| options.background, threshold);
+        }
+        if let Some(padding) = options.padding {
+            rgba = pad_image(&rgba, padding, options.padding_color);
+        }
+        Ok(rgba)
+    }
+
+    /// Prepares `image` and writes it to `out`
+    ///
+    /// Accepts a [`DynamicImage`] or any image buffer it converts from,
+    /// such as an [`RgbaImage`]
+    pub fn render(&self, image: impl Into<DynamicImage>, out: &mut dyn Write) -> Result<()> {
+        let rgba = self.prepare(image.into())?;
+        self.draw(&rgba, out)
+    }
+
+    /// Writes an image returned by [`Renderer::prepare`] to `out`
+    pub fn draw(&self, image: &RgbaImage, out: &mut dyn Write) -> Result<()> {
+        match self.options.border {
+            Some(border) => self.render_framed(image, border, out),
+            None => self.backend.render(image, out),
+        }
+    }
+
+    /// Like [`Renderer::draw`], for frame `index` of an animation that is
+    /// redrawn from a cursor position saved with DECSC, as
+    /// [`animation::play`](crate::animation::play) does
+    ///
+    /// Every frame replaces the previous one. Frames of pixel protocols are
+    /// placed by restoring the saved position instead of saving their own.
+    pub fn draw_for_playback(
+        &self,
+        image: &RgbaImage,
+        index: usize,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        match self.options.border {
+            Some(border) if self.options.protocol == Protocol::Blocks => {
+                self.render_framed(image, border, out)
+            }
+            Some(border) => {
+                self.write_box(image, border, out)?;
+                write!(out, "\x1b8\x1b[1B\x1b[1C")?;
+                self.backend.render_frame(image, index, out)
+            }
+            None => self.backend.render_frame(image, index, out),
+        }
+    }
+
+    /// Terminal columns taken up by a prepared image, including its frame
+    pub fn columns(&self, image: &RgbaImage) -> u32 {
+        self.cells(image).0 + self.frame()
+    }
+
+    /// Terminal rows taken up by a prepared image, including its frame
+    pub fn rows(&self, image: &RgbaImage) -> u32 {
+        self.cells(image).1 + self.frame()
+    }
+
+    /// Cells added on each axis by the frame
+    fn frame(&self) -> u32 {
+        if self.options.border.is_some() {
+            2
+        } else {
+            0
+        }
+    }
+
+    /// Terminal cells covered by a prepared image as (columns, rows)
+    fn cells(&self, image: &RgbaImage) -> (u32, u32) {
+        let (cell_width, cell_height) = self.cell_size();
+        let natural = (
+            image.width().div_ceil(cell_width),
+            image.height().div_ceil(cell_height),
+        );
+        if !matches!(self.options.protocol, Protocol::Kitty | Protocol::Iterm) {
+            return natural;
+        }
+
+        // With one side given, the terminal keeps the aspect ratio for the other
+        let (width, height) = (
+            image.width().max(1) as u64 * cell_height as u64,
+            image.height().max(1) as u64 * cell_width as u64,
+        );
+        match (self.options.columns, self.options.rows) {
+            (Some(columns), Some(rows)) => (columns, rows),
+            (Some(columns), None) => (columns, (columns as u64 * height).div_ceil(width) as u32),
+            (None, Some(rows)) => ((rows as u64 * width).div_ceil(height) as u32, rows),
+            (None, None) => natural,
+        }
+    }
+
+    /// Draws a prepared image inside a box of `border` characters
+    fn render_framed(&self, image: &RgbaImage, border: Border, out: &mut dyn Write) -> Result<()> {
+        let mut output = Vec::new();
+        self.backend.render(image, &mut output)?;
+
+        if self.options.protocol == Protocol::Blocks {
+            let line = self.border_line(image, border);
+            let [top_left, top_right, bottom_right, bottom_left, _, vertical] = border.chars();
+
+            writeln!(out, "{}{}{}", top_left, line, top_right)?;
+            for row in String::from_utf8(output)?.lines() {
+                writeln!(out, "{}{}{}", vertical, row, vertical)?;
+            }
+            write!(out, "{}{}{}", bottom_left, line, bottom_right)?;
+        } else {
+            // Pixel protocols can't be split into lines, so the box is drawn
+            // first and the image is placed inside it afterwards
+            self.write_box(image, border, out)?;
+            write!(out, "\x1b7\x1b[{}A\r\x1b[1C", self.cells(image).1)?;
+            out.write_all(&output)?;
+            write!(out, "\x1b8")?;
+        }
+        out.flush()?;
+
+        Ok(())
+    }
+
+    /// Draws an empty box around the cells of `image`, ending on its bottom line
+    fn write_box(&self, image: &RgbaImage, border: Border, out: &mut dyn Write) -> Result<()> {
+        let columns = self.cells(image).0 as usize;
+        let line = self.border_line(image, border);
+        let [top_left, top_right, bottom_right, bottom_left, _, vertical] = border.chars();
+
+        writeln!(out, "{}{}{}", top_left, line, top_right)?;
+        for _ in 0..self.cells(image).1 {
+            writeln!(out, "{}{:columns$}{}", vertical, "", vertical)?;
+        }
+        write!(out, "{}{}{}", bottom_left, line, bottom_right)?;
+
+        Ok(())
+    }
+
+    /// Horizontal edge of the box around `image`
+    fn border_line(&self, image: &RgbaImage, border: Border) -> String {
+        border.chars()[4]
+            .to_string()
+            .repeat(self.cells(image).0 as usize)
+    }
+
+    /// Like [`Renderer::render`], returning the escape sequences as a string
+    pub fn render_to_string(&self, image: impl Into<DynamicImage>) -> Result<String> {
+        let mut out = Vec::new();
+        self.render(image, &mut out)?;
+        Ok(String::from_utf8(out)?)
+    }
+}
+
+/// Blends pixels onto the `backdrop` color at their position and makes them
+/// opaque, pixels with alpha below `threshold` become fully transparent instead
+fn composite(image: &mut RgbaImage, backdrop: impl Fn(u32, u32) -> Option<[u8; 3]>, threshold: u8) {
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let alpha = pixel.0[3];
+        if alpha < threshold {
+            pixel.0[3] = 0;
+            continue;
+        }
+
+        if let Some(background) = backdrop(x, y) {
+            for (channel, back) in pixel.0.iter_mut().zip(background) {
+                let blended = *channel as u32 * alpha as u32 + back as u32 * (255 - alpha as u32);
+                *channel = ((blended + 127) / 255) as u8;
+            }
+        }
+        pixel.0[3] = 255;
+    }
+}
+
+/// Surrounds the image with pixels of `color`, or transparent ones
+fn pad_image(
+    image: &RgbaImage,
+    padding: (u32, u32, u32, u32),
+    color: Option<[u8; 3]>,
+) -> RgbaImage {
+    let (top, right, bottom, left) = padding;
+    let fill = color.map_or(Rgba([0; 4]), |[r, g, b]| Rgba([r, g, b, 255]));
+    let mut padded = RgbaImage::from_pixel(
+        image.width() + left + right,
+        image.height() + top + bottom,
+        fill,
+    );
+    imageops::replace(&mut padded, image, left as i64, top as i64);
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renderer(options: RenderOptions) -> Renderer {
+        Renderer::new(RenderOptions {
+            terminal: Some(TerminalSize {
+                columns: 80,
+                rows: 24,
+                cell_pixels: Some((10, 20)),
+            }),
+            ..options
+        })
+    }
+
+    /// 4x4 pixels: red above the anti-diagonal, blue below it and a
+    /// transparent bottom row
+    fn fixture() -> RgbaImage {
+        RgbaImage::from_fn(4, 4, |x, y| match (x + y, y) {
+            (_, 3) => Rgba([0; 4]),
+            (0..=2, _) => Rgba([255, 0, 0, 255]),
+            _ => Rgba([0, 0, 255, 255]),
+        })
+    }
+
+    fn snapshot(options: RenderOptions) -> String {
+        Renderer::new(options).render_to_string(fixture()).unwrap()
+    }
+
+    fn charset(charset: Charset) -> String {
+        snapshot(RenderOptions {
+            charset,
+            ..RenderOptions::default()
+        })
+    }
+
+    const R: &str = "\x1b[38;2;255;0;0m";
+    const B: &str = "\x1b[38;2;0;0;255m";
+    const BG_R: &str = "\x1b[48;2;255;0;0m";
+    const BG_B: &str = "\x1b[48;2;0;0;255m";
+    const RESET: &str = "\x1b[0m";
+
+    #[test]
+    fn half_snapshot() {
+        let expected = [
+            format!("{R}{BG_R}▀{RESET}{R}{BG_R}▀{RESET}{R}{BG_B}▀{RESET}{B}{BG_B}▀{RESET}"),
+            format!("{R}▀{RESET}{B}▀{RESET}{B}▀{RESET}{B}▀{RESET}"),
+        ];
+
+        assert_eq!(charset(Charset::Half), expected.join("\n"));
+    }
+
+    #[test]
+    fn quadrant_snapshot() {
+        let purple = "\x1b[38;2;127;0;127m";
+        let expected = [
+            format!("{R}{BG_R}█{RESET}{B}{BG_R}▟{RESET}"),
+            format!("{purple}▀{RESET}{B}▀{RESET}"),
+        ];
+
+        assert_eq!(charset(Charset::Quadrant), expected.join("\n"));
+    }
+
+    #[test]
+    fn sextant_snapshot() {
+        let expected = [
+            format!("{B}{BG_R}\u{1fb1e}{RESET}{B}{BG_R}\u{1fb3b}{RESET}"),
+            "  ".to_string(),
+        ];
+
+        assert_eq!(charset(Charset::Sextant), expected.join("\n"));
+    }
+
+    #[test]
+    fn braille_snapshot() {
+        let expected = "\x1b[38;2;212;0;42m⠿\x1b[0m\x1b[38;2;42;0;212m⠿\x1b[0m";
+
+        assert_eq!(charset(Charset::Braille), expected);
+    }
+
+    #[test]
+    fn ascii_snapshot() {
+        let purple = "\x1b[38;2;127;0;127m";
+        let expected = [
+            format!("{R}*{RESET}{R}*{RESET}{purple}={RESET}{B}={RESET}"),
+            format!("{R}*{RESET}{B}={RESET}{B}={RESET}{B}={RESET}"),
+        ];
+
+        assert_eq!(charset(Charset::Ascii), expected.join("\n"));
+    }
+
+    const PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAAAQAAAAECAYAAACp8Z5+AAAATElEQVR4Ae3AA6AkWZbG8f937o3IzKdyS2Oubdu2bdu2bdu2bWmMnpZKr54yMyLu+Xa3anqmhztr1a8azDMJU3kmYQAqgDDPhHhO/CP9PAUMUpzYWAAAAABJRU5ErkJggg==";
+
+    fn protocol(protocol: Protocol) -> String {
+        snapshot(RenderOptions {
+            protocol,
+            ..RenderOptions::default()
+        })
+    }
+
+    #[test]
+    fn sixel_snapshot() {
+        assert_eq!(
+            protocol(Protocol::Sixel),
+            "\x1bP0;1;0q\"1;1;4;4#0;2;0;0;100#1;2;100;0;0#0?CEF$#1FB@-\x1b\\"
+        );
+    }
+
+    #[test]
+    fn kitty_snapshot() {
+        let output = protocol(Protocol::Kitty);
+        // Image ids depend on the process, so only their presence is checked
+        let (start, rest) = output.split_once(",i=").unwrap();
+        let (id, rest) = rest.split_once(',').unwrap();
+
+        assert_eq!(start, "\x1b_Ga=T,f=100,q=2");
+        assert!(id.parse::<u32>().is_ok_and(|id| id > 0));
+        assert_eq!(rest, format!("m=0;{}\x1b\\", PNG));
+    }
+
+    #[test]
+    fn iterm_snapshot() {
+        assert_eq!(
+            protocol(Protocol::Iterm),
+            format!(
+                "\x1b]1337;File=inline=1;size=133;width=4px;height=4px;preserveAspectRatio=1:{}\x07",
+                PNG
+            )
+        );
+    }
+
+    /// Splits a line of blocks output into (escape sequences, character) cells
+    fn cells(line: &str) -> Vec<(String, char)> {
+        let mut cells = Vec::new();
+        let mut style = String::new();
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            if c != '\x1b' {
+                cells.push((std::mem::take(&mut style), c));
+                continue;
+            }
+            let sequence: String = chars.by_ref().take_while(|&c| c != 'm').collect();
+            if sequence != "[0" {
+                style += &format!("\x1b{}m", sequence);
+            }
+        }
+        cells
+    }
+
+    #[test]
+    fn odd_sizes() {
+        for (width, height) in [(1, 1), (1, 3), (3, 1), (2, 3)] {
+            let image = RgbaImage::from_pixel(width, height, Rgba([255, 0, 0, 255]));
+            let output = Renderer::new(RenderOptions::default())
+                .render_to_string(image)
+                .unwrap();
+            let lines: Vec<_> = output.lines().map(cells).collect();
+
+            assert_eq!(
+                lines.len(),
+                height.div_ceil(2) as usize,
+                "{}x{}",
+                width,
+                height
+            );
+            for line in &lines {
+                assert_eq!(line.len(), width as usize, "{}x{}", width, height);
+            }
+            let (last, full) = lines.split_last().unwrap();
+            for cell in full.iter().flatten() {
+                assert_eq!(*cell, (format!("{R}{BG_R}"), '▀'));
+            }
+            for cell in last {
+                assert_eq!(*cell, (R.to_string(), '▀'), "{}x{}", width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn odd_sizes_padded() {
+        for (width, height) in [(1, 1), (1, 3), (3, 1), (2, 3)] {
+            let image = RgbaImage::from_pixel(width, height, Rgba([255, 0, 0, 255]));
+            let output = Renderer::new(RenderOptions {
+                padding: Some((1, 1, 0, 1)),
+                ..RenderOptions::default()
+            })
+            .render_to_string(image)
+            .unwrap();
+            let lines: Vec<_> = output.lines().map(cells).collect();
+
+            // The top padding row pairs with the first image row, the rest pair up evenly
+            assert_eq!(
+                lines.len(),
+                (height + 1).div_ceil(2) as usize,
+                "{}x{}",
+                width,
+                height
+            );
+            for (y, line) in lines.iter().enumerate() {
+                assert_eq!(line.len(), width as usize + 2, "{}x{}", width, height);
+                assert_eq!(line[0], (String::new(), ' '));
+                assert_eq!(line[width as usize + 1], (String::new(), ' '));
+
+                let expected = match y {
+                    0 => (R.to_string(), '▄'),
+                    _ => (format!("{R}{BG_R}"), '▀'),
+                };
+                for cell in &line[1..=width as usize] {
+                    assert_eq!(*cell, expected, "{}x{} row {}", width, height, y);
+                }
+            }
+        }
+    }
+
+    /// Red pixels with alpha 0, 1, 127, 128 and 255, prepared with `options`
+    fn composited(options: RenderOptions) -> Vec<[u8; 4]> {
+        let image = RgbaImage::from_fn(5, 1, |x, _| {
+            Rgba([255, 0, 0, [0, 1, 127, 128, 255][x as usize]])
+        });
+        Renderer::new(options)
+            .prepare(image.into())
+            .unwrap()
+            .pixels()
+            .map(|pixel| pixel.0)
+            .collect()
+    }
+
+    #[test]
+    fn background_blends() {
+        let pixels = composited(RenderOptions {
+            background: Some([0, 0, 255]),
+            ..RenderOptions::default()
+        });
+
+        // Without a threshold only fully transparent pixels stay transparent
+        assert_eq!(pixels[0][3], 0);
+        assert_eq!(
+            pixels[1..],
+            [
+                [1, 0, 254, 255],
+                [127, 0, 128, 255],
+                [128, 0, 127, 255],
+                [255, 0, 0, 255]
+            ]
+        );
+    }
+
+    #[test]
+    fn threshold_without_background() {
+        let pixels = composited(RenderOptions {
+            alpha_threshold: Some(128),
+            ..RenderOptions::default()
+        });
+
+        let alpha: Vec<_> = pixels.iter().map(|pixel| pixel[3]).collect();
+        assert_eq!(alpha, [0, 0, 0, 255, 255]);
+        // Kept pixels are made opaque without changing their color
+        assert_eq!(pixels[3], [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn threshold_with_background() {
+        let pixels = composited(RenderOptions {
+            background: Some([0, 0, 255]),
+            alpha_threshold: Some(128),
+            ..RenderOptions::default()
+        });
+
+        let alpha: Vec<_> = pixels.iter().map(|pixel| pixel[3]).collect();
+        assert_eq!(alpha, [0, 0, 0, 255, 255]);
+        assert_eq!(pixels[3], [128, 0, 127, 255]);
+    }
+
+    #[test]
+    fn checkerboard_squares() {
+        let (light, dark) = ([200; 3], [100; 3]);
+        let board = |size| {
+            renderer(RenderOptions {
+                checkerboard: Some(Checkerboard {
+                    size,
+                    colors: (light, dark),
+                }),
+                protocol: Protocol::Kitty,
+                ..RenderOptions::default()
+            })
+            .prepare(RgbaImage::new(90, 50).into())
+            .unwrap()
+        };
+        let color = |image: &RgbaImage, x, y| {
+            let [r, g, b, a] = image.get_pixel(x, y).0;
+            assert_eq!(a, 255);
+            [r, g, b]
+        };
+
+        // Squares are one row of 20 pixel cells high, and as wide
+        let image = board(1);
+        assert_eq!(color(&image, 0, 0), light);
+        assert_eq!(color(&image, 19, 19), light);
+        assert_eq!(color(&image, 20, 0), dark);
+        assert_eq!(color(&image, 0, 20), dark);
+        assert_eq!(color(&image, 20, 20), light);
+        assert_eq!(color(&image, 40, 0), light);
+        assert_eq!(color(&image, 89, 49), light);
+
+        let image = board(2);
+        assert_eq!(color(&image, 39, 39), light);
+        assert_eq!(color(&image, 40, 0), dark);
+        assert_eq!(color(&image, 0, 40), dark);
+        assert_eq!(color(&image, 80, 0), light);
+    }
+
+    #[test]
+    fn thread_safe() {
+        fn assert_thread_safe<T: Send + Sync>() {}
+        assert_thread_safe::<Renderer>();
+    }
+
+    #[test]
+    fn placement_columns() {
+        let image = RgbaImage::new(40, 40);
+        let kitty = |columns, rows, border| {
+            renderer(RenderOptions {
+                protocol: Protocol::Kitty,
+                columns,
+                rows,
+                border,
+                ..RenderOptions::default()
+            })
+        };
+
+        assert_eq!(kitty(None, None, None).columns(&image), 4);
+        assert_eq!(kitty(Some(9), None, None).columns(&image), 9);
+        assert_eq!(
+            kitty(Some(9), None, Some(Border::Solid)).columns(&image),
+            11
+        );
+        assert_eq!(kitty(None, Some(3), None).columns(&image), 6);
+        assert_eq!(kitty(Some(9), Some(3), None).cells(&image), (9, 3));
+        assert_eq!(kitty(Some(8), None, None).cells(&image), (8, 4));
+    }
+
+    #[test]
+    fn frame_fits() {
+        for border in [None, Some(Border::Rounded)] {
+            let renderer = Renderer::new(RenderOptions {
+                fit: Fit {
+                    max_width: Some(20),
+                    max_height: Some(6),
+                    ..Fit::default()
+                },
+                border,
+                ..RenderOptions::default()
+            });
+            let image = renderer.prepare(RgbaImage::new(100, 10).into()).unwrap();
+
+            assert_eq!(renderer.columns(&image), 20);
+            assert!(renderer.rows(&image) <= 6);
+        }
+    }
+
+    #[test]
+    fn playback_frame() {
+        let image = RgbaImage::from_pixel(20, 40, Rgba([255, 0, 0, 255]));
+        let renderer = renderer(RenderOptions {
+            protocol: Protocol::Sixel,
+            border: Some(Border::Solid),
+            ..RenderOptions::default()
+        });
+        let mut still = Vec::new();
+        renderer.draw(&image, &mut still).unwrap();
+        let mut frame = Vec::new();
+        renderer.draw_for_playback(&image, 0, &mut frame).unwrap();
+        let (still, frame) = (
+            String::from_utf8(still).unwrap(),
+            String::from_utf8(frame).unwrap(),
+        );
+
+        assert_eq!((renderer.columns(&image), renderer.rows(&image)), (4, 4));
+        let edges = "┌──┐\n│  │\n│  │\n└──┘";
+        assert!(still.starts_with(&format!("{}\x1b7\x1b[2A\r\x1b[1C\x1bP", edges)));
+        assert!(still.ends_with("\x1b\\\x1b8"));
+        assert!(frame.starts_with(&format!("{}\x1b8\x1b[1B\x1b[1C\x1bP", edges)));
+        assert!(!frame.contains("\x1b7"));
+    }
+}
@@ -0,0 +1,188 @@
+use crate::detect::TerminalSize;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView};
+
+/// Resampling filter
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Filter {
+    /// Nearest neighbour, no smoothing
+    Nearest,
+    /// Linear
+    Triangle,
+    /// Cubic
+    #[default]
+    CatmullRom,
+    /// Gaussian, softest
+    Gaussian,
+    /// Lanczos with window 3, sharpest
+    Lanczos3,
+}
+
+impl From<Filter> for FilterType {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Nearest => FilterType::Nearest,
+            Filter::Triangle => FilterType::Triangle,
+            Filter::CatmullRom => FilterType::CatmullRom,
+            Filter::Gaussian => FilterType::Gaussian,
+            Filter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Size constraints in terminal cells
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Fit {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+}
+
+impl Fit {
+    /// Also limits the size to the terminal window, keeping a line free for the prompt
+    pub fn within(mut self, terminal: TerminalSize) -> Self {
+        let rows = terminal.rows.saturating_sub(1).max(1);
+        self.max_width = Some(
+            self.max_width
+                .map_or(terminal.columns, |w| w.min(terminal.columns)),
+        );
+        self.max_height = Some(self.max_height.map_or(rows, |h| h.min(rows)));
+        self
+    }
+
+    /// Scale factor that brings an image of `width` x `height` pixels within
+    /// the constraints, given the pixels covered by one cell
+    ///
+    /// `padding` pixels and `frame` cells on each axis are taken off the
+    /// room left for the image
+    pub fn factor(
+        &self,
+        width: u32,
+        height: u32,
+        (cell_width, cell_height): (u32, u32),
+        padding: Option<(u32, u32, u32, u32)>,
+        frame: u32,
+    ) -> f32 {
+        let (top, right, bottom, left) = padding.unwrap_or_default();
+        let to_width = |columns: u32| {
+            (columns.saturating_sub(frame).saturating_mul(cell_width))
+                .saturating_sub(left.saturating_add(right))
+                .max(1) as f32
+                / width as f32
+        };
+        let to_height = |rows: u32| {
+            (rows.saturating_sub(frame).saturating_mul(cell_height))
+                .saturating_sub(top.saturating_add(bottom))
+                .max(1) as f32
+                / height as f32
+        };
+
+        let mut factor = match (self.width, self.height) {
+            (Some(columns), Some(rows)) => to_width(columns).min(to_height(rows)),
+            (Some(columns), None) => to_width(columns),
+            (None, Some(rows)) => to_height(rows),
+            (None, None) => 1.0,
+        };
+
+        if let Some(columns) = self.max_width {
+            factor = factor.min(to_width(columns));
+        }
+        if let Some(rows) = self.max_height {
+            factor = factor.min(to_height(rows));
+        }
+
+        factor
+    }
+}
+
+pub fn scale_image(image: DynamicImage, scale: f32, filter: FilterType) -> DynamicImage {
+    let nwidth = (image.width() as f32 * scale).round().max(1.0);
+    let nheight = (image.height() as f32 * scale).round().max(1.0);
+
+    image.resize(nwidth as u32, nheight as u32, filter)
+}
+
+/// Scales by the closest whole multiple or divisor not above `scale`
+pub fn scale_image_integer(image: DynamicImage, scale: f32) -> DynamicImage {
+    let (nwidth, nheight) = if scale >= 1.0 {
+        let multiple = scale.floor() as u32;
+        (image.width() * multiple, image.height() * multiple)
+    } else {
+        let divisor = ((1.0 / scale).ceil() as u32).max(1);
+        (
+            (image.width() / divisor).max(1),
+            (image.height() / divisor).max(1),
+        )
+    };
+
+    if (nwidth, nheight) == image.dimensions() {
+        return image;
+    }
+    image.resize_exact(nwidth, nheight, FilterType::Nearest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    fn integer(scale: f32) -> (u32, u32) {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(12, 8));
+        scale_image_integer(image, scale).dimensions()
+    }
+
+    #[test]
+    fn fit_factor() {
+        let fit = Fit {
+            max_width: Some(10),
+            max_height: Some(10),
+            ..Fit::default()
+        };
+
+        assert_eq!(fit.factor(40, 10, (1, 2), None, 0), 0.25);
+        assert_eq!(fit.factor(40, 10, (1, 2), Some((0, 1, 0, 1)), 0), 0.2);
+        assert_eq!(fit.factor(40, 10, (1, 2), None, 2), 0.2);
+        assert_eq!(fit.factor(40, 10, (1, 2), Some((0, 1, 0, 1)), 2), 0.15);
+        assert_eq!(fit.factor(4, 4, (1, 2), None, 2), 1.0);
+    }
+
+    #[test]
+    fn fit_factor_saturates() {
+        let fit = Fit {
+            width: Some(u32::MAX),
+            height: Some(u32::MAX),
+            ..Fit::default()
+        };
+        let padding = Some((u32::MAX, u32::MAX, 1, 1));
+
+        assert_eq!(fit.factor(1, 1, (10, 20), None, 2), u32::MAX as f32);
+        assert_eq!(fit.factor(1, 1, (10, 20), padding, 0), 1.0);
+    }
+
+    #[test]
+    fn fit_frame_larger_than_room() {
+        let fit = Fit {
+            width: Some(2),
+            ..Fit::default()
+        };
+
+        assert_eq!(fit.factor(10, 10, (1, 2), None, 2), 0.1);
+    }
+
+    #[test]
+    fn integer_scale() {
+        assert_eq!(integer(1.0), (12, 8));
+        assert_eq!(integer(2.7), (24, 16));
+        assert_eq!(integer(0.5), (6, 4));
+        assert_eq!(integer(0.4), (4, 2));
+        assert_eq!(integer(0.01), (1, 1));
+    }
+
+    #[test]
+    fn integer_scale_out_of_range() {
+        assert_eq!(integer(0.0), (1, 1));
+        assert_eq!(integer(-1.0), (12, 8));
+        assert_eq!(integer(f32::NAN), (12, 8));
+    }
+}
@@ -0,0 +1,264 @@
+use anyhow::{anyhow, Result};
+use image::DynamicImage;
+
+/// Crop, rotation and flip, applied in that order before scaling
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Transform {
+    pub crop: Option<Crop>,
+    pub rotate: Option<Rotation>,
+    pub flip: Option<Flip>,
+}
+
+impl Transform {
+    /// Fails when the crop region starts outside the image
+    pub fn apply(&self, mut image: DynamicImage) -> Result<DynamicImage> {
+        if let Some(crop) = self.crop {
+            let (x, y, width, height) =
+                crop.region(image.width(), image.height()).ok_or_else(|| {
+                    anyhow!(
+                        "Crop region starts outside the image of {}x{}",
+                        image.width(),
+                        image.height()
+                    )
+                })?;
+            image = image.crop_imm(x, y, width, height);
+        }
+
+        image = match self.rotate {
+            Some(Rotation::Rotate90) => image.rotate90(),
+            Some(Rotation::Rotate180) => image.rotate180(),
+            Some(Rotation::Rotate270) => image.rotate270(),
+            None => image,
+        };
+
+        Ok(match self.flip {
+            Some(Flip::Horizontal) => image.fliph(),
+            Some(Flip::Vertical) => image.flipv(),
+            None => image,
+        })
+    }
+}
+
+/// Clockwise rotation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Rotation {
+    #[value(name = "90")]
+    Rotate90,
+    #[value(name = "180")]
+    Rotate180,
+    #[value(name = "270")]
+    Rotate270,
+}
+
+/// Mirroring axis
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Flip {
+    /// Left to right
+    #[value(name = "h", alias = "horizontal")]
+    Horizontal,
+    /// Top to bottom
+    #[value(name = "v", alias = "vertical")]
+    Vertical,
+}
+
+/// A distance in pixels or relative to the image size
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Length {
+    Pixels(u32),
+    Percent(f32),
+}
+
+impl Length {
+    fn resolve(self, total: u32) -> u32 {
+        match self {
+            Self::Pixels(pixels) => pixels,
+            Self::Percent(percent) => (total as f32 * percent / 100.0).round() as u32,
+        }
+    }
+}
+
+/// Where a crop region is placed inside the image
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Gravity {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+/// Region of the image to keep
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Crop {
+    /// Region with its top left corner at `x`, `y`
+    At {
+        x: Length,
+        y: Length,
+        width: Length,
+        height: Length,
+    },
+    /// Region placed against an edge, a corner or the center
+    Gravity {
+        width: Length,
+        height: Length,
+        gravity: Gravity,
+    },
+}
+
+impl Crop {
+    /// Region as (x, y, width, height) within an image of `width` x `height`
+    ///
+    /// Regions reaching past the image are cut at its edges and never end up
+    /// smaller than a pixel. Returns `None` for regions starting outside the
+    /// image.
+    pub fn region(self, width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+        let (x, y, crop_width, crop_height) = match self {
+            Self::At {
+                x,
+                y,
+                width: w,
+                height: h,
+            } => (
+                x.resolve(width),
+                y.resolve(height),
+                w.resolve(width),
+                h.resolve(height),
+            ),
+            Self::Gravity {
+                width: w,
+                height: h,
+                gravity,
+            } => {
+                let (w, h) = (w.resolve(width).min(width), h.resolve(height).min(height));
+                let (left, center, right) = (0, (width - w) / 2, width - w);
+                let (top, middle, bottom) = (0, (height - h) / 2, height - h);
+                let (x, y) = match gravity {
+                    Gravity::TopLeft => (left, top),
+                    Gravity::Top => (center, top),
+                    Gravity::TopRight => (right, top),
+                    Gravity::Left => (left, middle),
+                    Gravity::Center => (center, middle),
+                    Gravity::Right => (right, middle),
+                    Gravity::BottomLeft => (left, bottom),
+                    Gravity::Bottom => (center, bottom),
+                    Gravity::BottomRight => (right, bottom),
+                };
+                (x, y, w, h)
+            }
+        };
+
+        if x >= width || y >= height {
+            return None;
+        }
+        let crop_width = crop_width.min(width - x).max(1);
+        let crop_height = crop_height.min(height - y).max(1);
+        Some((x, y, crop_width, crop_height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgba, RgbaImage};
+    use Length::{Percent, Pixels};
+
+    fn at(x: Length, y: Length, width: Length, height: Length) -> Crop {
+        Crop::At {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn gravity(width: Length, height: Length, gravity: Gravity) -> Crop {
+        Crop::Gravity {
+            width,
+            height,
+            gravity,
+        }
+    }
+
+    #[test]
+    fn lengths() {
+        assert_eq!(Pixels(7).resolve(100), 7);
+        assert_eq!(Percent(50.0).resolve(100), 50);
+        assert_eq!(Percent(50.0).resolve(7), 4);
+        assert_eq!(Percent(100.0).resolve(33), 33);
+        assert_eq!(Percent(0.0).resolve(33), 0);
+    }
+
+    #[test]
+    fn regions() {
+        let crop = at(Pixels(2), Pixels(3), Pixels(4), Pixels(5));
+        assert_eq!(crop.region(100, 50), Some((2, 3, 4, 5)));
+
+        let crop = at(Percent(10.0), Percent(50.0), Percent(50.0), Percent(25.0));
+        assert_eq!(crop.region(100, 40), Some((10, 20, 50, 10)));
+    }
+
+    #[test]
+    fn every_gravity() {
+        let region = |g| gravity(Pixels(4), Percent(50.0), g).region(10, 8);
+
+        assert_eq!(region(Gravity::TopLeft), Some((0, 0, 4, 4)));
+        assert_eq!(region(Gravity::Top), Some((3, 0, 4, 4)));
+        assert_eq!(region(Gravity::TopRight), Some((6, 0, 4, 4)));
+        assert_eq!(region(Gravity::Left), Some((0, 2, 4, 4)));
+        assert_eq!(region(Gravity::Center), Some((3, 2, 4, 4)));
+        assert_eq!(region(Gravity::Right), Some((6, 2, 4, 4)));
+        assert_eq!(region(Gravity::BottomLeft), Some((0, 4, 4, 4)));
+        assert_eq!(region(Gravity::Bottom), Some((3, 4, 4, 4)));
+        assert_eq!(region(Gravity::BottomRight), Some((6, 4, 4, 4)));
+    }
+
+    #[test]
+    fn clamped_at_edges() {
+        // Regions reaching past the image are cut at its edges
+        let crop = at(Pixels(6), Pixels(6), Pixels(10), Pixels(10));
+        assert_eq!(crop.region(8, 8), Some((6, 6, 2, 2)));
+        let crop = gravity(Pixels(20), Percent(100.0), Gravity::BottomRight);
+        assert_eq!(crop.region(8, 4), Some((0, 0, 8, 4)));
+
+        // And are never empty
+        let crop = at(Pixels(7), Pixels(0), Pixels(0), Percent(0.0));
+        assert_eq!(crop.region(8, 8), Some((7, 0, 1, 1)));
+        let crop = gravity(Pixels(0), Pixels(0), Gravity::Center);
+        assert_eq!(crop.region(8, 8), Some((4, 4, 1, 1)));
+    }
+
+    #[test]
+    fn outside_the_image() {
+        let crop = at(Pixels(8), Pixels(0), Pixels(2), Pixels(2));
+        assert_eq!(crop.region(8, 8), None);
+        let crop = at(Pixels(0), Percent(100.0), Pixels(2), Pixels(2));
+        assert_eq!(crop.region(8, 8), None);
+
+        let transform = Transform {
+            crop: Some(crop),
+            ..Transform::default()
+        };
+        assert!(transform.apply(RgbaImage::new(8, 8).into()).is_err());
+    }
+
+    #[test]
+    fn crop_then_rotate_then_flip() {
+        // Columns numbered 0 to 3 by their red channel
+        let image = RgbaImage::from_fn(4, 2, |x, _| Rgba([x as u8, 0, 0, 255]));
+        let transform = Transform {
+            crop: Some(at(Pixels(1), Pixels(0), Pixels(2), Pixels(2))),
+            rotate: Some(Rotation::Rotate90),
+            flip: Some(Flip::Vertical),
+        };
+
+        let image = transform.apply(image.into()).unwrap();
+        assert_eq!(image.dimensions(), (2, 2));
+        // Rotating puts column 1 on top, flipping then moves it to the bottom
+        assert_eq!(image.get_pixel(0, 0).0[0], 2);
+        assert_eq!(image.get_pixel(0, 1).0[0], 1);
+    }
+}